@@ -0,0 +1,625 @@
+use bevy::ecs::system::EntityCommands;
+use bevy::prelude::*;
+
+use crate::audio::SoundEvent;
+use crate::entities::{FieldValue, LdtkEntity, RegisterLdtkEntity};
+use crate::level::Level;
+use crate::physics::{
+    probe_y, snap_direction, standing, CollisionKind, DashState, Grounded, Movement, PhysicsSet,
+    Velocity,
+};
+use crate::player::{PlayerDeath, PlayerMarker};
+use crate::state::{on_level_start, GameState, LevelEntity};
+use crate::util::*;
+
+pub struct AbilitiesPlugin;
+
+const PICKUP_SIZE: Vec2 = Vec2::splat(10.);
+const BREAKABLE_COLOR: Color = Color::rgb(0.6, 0.4, 0.25);
+const ROPE_COLOR: Color = Color::rgb(0.85, 0.8, 0.7);
+const ROPE_WIDTH: f32 = 1.;
+
+impl Plugin for AbilitiesPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_ldtk_entity("AbilityPickup", spawn_ability_pickup)
+            .register_ldtk_entity("BreakableBlock", spawn_breakable_block);
+        for schedule in on_level_start() {
+            app.add_systems(schedule, add_breakable_colliders);
+        }
+        app.add_systems(
+            Update,
+            (
+                (
+                    start_ground_pound,
+                    double_jump.after(start_ground_pound),
+                    fire_grapple,
+                    reel_grapple.after(fire_grapple),
+                )
+                    .in_set(PhysicsSet::PreStep),
+                (
+                    glide,
+                    land_ground_pound,
+                    swing_grapple,
+                    draw_rope.after(swing_grapple),
+                )
+                    .in_set(PhysicsSet::PostStep),
+                (
+                    pick_up_abilities,
+                    release_grapple_on_death.run_if(on_event::<PlayerDeath>()),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            ),
+        );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ability {
+    DoubleJump,
+    Glide,
+    GroundPound,
+    Grapple,
+}
+
+impl Ability {
+    // enum values of the "Ability" field on AbilityPickup
+    pub fn from_identifier(identifier: &str) -> Option<Ability> {
+        match identifier {
+            "DoubleJump" => Some(Ability::DoubleJump),
+            "Glide" => Some(Ability::Glide),
+            "GroundPound" => Some(Ability::GroundPound),
+            "Grapple" => Some(Ability::Grapple),
+            _ => None,
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Ability::DoubleJump => Color::rgb(0.55, 0.85, 1.),
+            Ability::Glide => Color::rgb(0.75, 1., 0.6),
+            Ability::GroundPound => Color::rgb(1., 0.6, 0.3),
+            Ability::Grapple => ROPE_COLOR,
+        }
+    }
+
+    pub fn grant(self, entity: &mut EntityCommands) {
+        match self {
+            Ability::DoubleJump => entity.insert(DoubleJump::default()),
+            Ability::Glide => entity.insert(Glide),
+            Ability::GroundPound => entity.insert(GroundPound::default()),
+            Ability::Grapple => entity.insert(Grapple::default()),
+        };
+    }
+
+    pub fn revoke(self, entity: &mut EntityCommands) {
+        match self {
+            Ability::DoubleJump => entity.remove::<DoubleJump>(),
+            Ability::Glide => entity.remove::<Glide>(),
+            Ability::GroundPound => entity.remove::<GroundPound>(),
+            Ability::Grapple => entity.remove::<Grapple>(),
+        };
+    }
+}
+
+// one extra jump in the air, given back on landing
+#[derive(Component, Default)]
+pub struct DoubleJump {
+    pub used: bool,
+}
+
+// holding jump while falling caps the fall speed
+#[derive(Component)]
+pub struct Glide;
+
+// down and jump in the air slams into the ground and breaks breakable blocks
+#[derive(Component, Default)]
+pub struct GroundPound {
+    pub active: bool,
+}
+
+// hook fired toward the aimed direction, the player swings from where it
+// lands while the button is held
+#[derive(Component, Default)]
+pub struct Grapple {
+    pub anchor: Option<Vec2>,
+    pub length: f32,
+    rope: Option<Entity>,
+}
+
+#[derive(Component)]
+pub struct RopeMarker;
+
+#[derive(Component)]
+pub struct AbilityPickup {
+    pub ability: Ability,
+    // takes the ability away instead of granting it
+    pub revoke: bool,
+}
+
+#[derive(Component)]
+pub struct Breakable;
+
+fn spawn_ability_pickup(entity: &mut EntityCommands, item: &LdtkEntity, _: &AssetServer) {
+    let ability = match item.fields.0.get("Ability") {
+        Some(FieldValue::Enum(value)) => Ability::from_identifier(value),
+        _ => None,
+    };
+    let Some(ability) = ability else {
+        warn!("AbilityPickup {} has no known Ability field", item.iid);
+        return;
+    };
+    let revoke = matches!(item.fields.0.get("Revoke"), Some(FieldValue::Bool(true)));
+    entity.insert((
+        Sprite {
+            color: ability.color(),
+            custom_size: Some(PICKUP_SIZE),
+            ..default()
+        },
+        Handle::<Image>::default(),
+        AbilityPickup { ability, revoke },
+    ));
+}
+
+fn spawn_breakable_block(entity: &mut EntityCommands, item: &LdtkEntity, _: &AssetServer) {
+    entity.insert((
+        Sprite {
+            color: BREAKABLE_COLOR,
+            custom_size: Some(item.size),
+            ..default()
+        },
+        Handle::<Image>::default(),
+        Breakable,
+    ));
+}
+
+// the level resource outlives a run, blocks broken last time are added back
+fn add_breakable_colliders(mut level: ResMut<Level>) {
+    level
+        .bypass_change_detection()
+        .add_entity_colliders(&["BreakableBlock"], CollisionKind::Breakable);
+}
+
+fn pick_up_abilities(
+    mut commands: Commands,
+    player: Query<(Entity, &Transform), With<PlayerMarker>>,
+    query: Query<(Entity, &AbilityPickup, &Transform)>,
+    mut sound_events: EventWriter<SoundEvent>,
+) {
+    let Ok((player, player_transform)) = player.get_single() else {
+        return;
+    };
+    for (entity, pickup, transform) in query.iter() {
+        let delta = (player_transform.translation - transform.translation)
+            .truncate()
+            .abs();
+        if delta.x >= BLOCK_SIZE.x || delta.y >= BLOCK_SIZE.y {
+            continue;
+        }
+        let mut player = commands.entity(player);
+        if pickup.revoke {
+            pickup.ability.revoke(&mut player);
+        } else {
+            pickup.ability.grant(&mut player);
+        }
+        sound_events.send(SoundEvent::Pickup);
+        commands.entity(entity).despawn();
+    }
+}
+
+fn start_ground_pound(
+    level: Res<Level>,
+    mut query: Query<(
+        &Transform,
+        &HitBox,
+        &Grounded,
+        &mut Velocity,
+        &mut Movement,
+        &mut GroundPound,
+    )>,
+) {
+    for (transform, hitbox, grounded, mut vel, mut movement, mut pound) in query.iter_mut() {
+        if pound.active
+            || movement.climbing
+            || movement.grabbing
+            || movement.swimming
+            || !movement.jump
+            || movement.directional.y >= 0.
+            || !matches!(movement.dash.status, DashState::Ready)
+            || grounded.0
+            || standing(&level.colliders, transform.translation, *hitbox)
+        {
+            continue;
+        }
+        pound.active = true;
+        movement.jump = false;
+        vel.0 = Vec2::new(0., -GROUND_POUND_SPEED);
+    }
+}
+
+fn land_ground_pound(
+    mut level: ResMut<Level>,
+    mut commands: Commands,
+    mut query: Query<(
+        &Transform,
+        &HitBox,
+        &Grounded,
+        &mut Velocity,
+        &mut GroundPound,
+    )>,
+    blocks: Query<(Entity, &Transform), With<Breakable>>,
+    mut sound_events: EventWriter<SoundEvent>,
+) {
+    for (transform, hitbox, grounded, mut vel, mut pound) in query.iter_mut() {
+        if !pound.active {
+            continue;
+        }
+        if !grounded.0 {
+            // keep slamming down at full speed
+            vel.0 = Vec2::new(0., -GROUND_POUND_SPEED);
+            continue;
+        }
+        pound.active = false;
+        vel.0 = Vec2::ZERO;
+        sound_events.send(SoundEvent::Land);
+        let below = probe_y(transform.translation, *hitbox, -hitbox.half_size.y);
+        let Some(hit) = level
+            .colliders
+            .iter()
+            .position(|item| item.kind == CollisionKind::Breakable && item.contains(below))
+        else {
+            continue;
+        };
+        // not a level change, speedrun splits and the music follow those
+        let broken = level.bypass_change_detection().colliders.remove(hit);
+        for (entity, block) in blocks.iter() {
+            if broken.contains(block.translation.truncate()) {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+fn double_jump(
+    level: Res<Level>,
+    mut sound_events: EventWriter<SoundEvent>,
+    mut query: Query<(
+        &Transform,
+        &HitBox,
+        &Grounded,
+        &mut Velocity,
+        &mut Movement,
+        &mut DoubleJump,
+    )>,
+) {
+    for (transform, hitbox, grounded, mut vel, mut movement, mut double_jump) in query.iter_mut() {
+        // a landing can stop short of the floor, so standing alone misses it
+        let on_ground = grounded.0 || standing(&level.colliders, transform.translation, *hitbox);
+        if movement.swimming || on_ground {
+            double_jump.used = false;
+            continue;
+        }
+        // dash jumps get the first go at a jump pressed during a dash
+        let dashing =
+            matches!(movement.dash.status, DashState::Dashing) || movement.dash.grace > 0.;
+        if !movement.jump || double_jump.used || dashing || movement.climbing || movement.grabbing {
+            continue;
+        }
+        double_jump.used = true;
+        movement.jump = false;
+        vel.0.y = JMP_VEL_PLAYER * DOUBLE_JUMP_HEIGHT;
+        sound_events.send(SoundEvent::Jump);
+    }
+}
+
+fn glide(mut query: Query<(&mut Velocity, &Movement, Option<&GroundPound>), With<Glide>>) {
+    for (mut vel, movement, pound) in query.iter_mut() {
+        let pounding = pound.is_some_and(|pound| pound.active);
+        if movement.jump_held
+            && !pounding
+            && !matches!(movement.dash.status, DashState::Dashing)
+            && vel.0.y < -GLIDE_FALL_SPEED
+        {
+            vel.0.y = -GLIDE_FALL_SPEED;
+        }
+    }
+}
+
+fn detach_grapple(commands: &mut Commands, movement: &mut Movement, grapple: &mut Grapple) {
+    grapple.anchor = None;
+    movement.swinging = false;
+    if let Some(rope) = grapple.rope.take() {
+        commands.entity(rope).despawn();
+    }
+}
+
+fn fire_grapple(
+    mut commands: Commands,
+    level: Res<Level>,
+    mut query: Query<(&Transform, &mut Movement, &mut Grapple)>,
+) {
+    for (transform, mut movement, mut grapple) in query.iter_mut() {
+        if !movement.grapple || grapple.anchor.is_some() || movement.climbing || movement.grabbing {
+            continue;
+        }
+        // without a direction the hook goes up and forward
+        let aim = snap_direction(movement.directional)
+            .unwrap_or_else(|| Vec2::new(movement.facing, 1.).normalize());
+        let origin = transform.translation.truncate();
+        let Some(hit) = level.raycast(origin, aim, GRAPPLE_RANGE) else {
+            continue;
+        };
+        grapple.anchor = Some(hit.point);
+        grapple.length = hit.distance.max(GRAPPLE_MIN_LENGTH);
+        movement.swinging = true;
+        let rope = commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: ROPE_COLOR,
+                    ..default()
+                },
+                ..default()
+            },
+            RopeMarker,
+            LevelEntity,
+        ));
+        grapple.rope = Some(rope.id());
+    }
+}
+
+// up and down reel the rope in and out, letting go or dashing releases it
+fn reel_grapple(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(&mut Movement, &mut Grapple)>,
+) {
+    for (mut movement, mut grapple) in query.iter_mut() {
+        if grapple.anchor.is_none() {
+            continue;
+        }
+        let dashing = !matches!(movement.dash.status, DashState::Ready);
+        if !movement.grapple_held || dashing || movement.climbing || movement.grabbing {
+            detach_grapple(&mut commands, &mut movement, &mut grapple);
+            continue;
+        }
+        grapple.length = (grapple.length
+            - movement.directional.y * GRAPPLE_REEL_SPEED * time.delta_seconds())
+        .clamp(GRAPPLE_MIN_LENGTH, GRAPPLE_RANGE);
+    }
+}
+
+// pendulum constraint, a taut rope pulls the player back onto the circle and
+// keeps only the velocity along it, so letting go keeps the swing speed. The
+// pull stops where the hitbox meets a wall
+fn swing_grapple(
+    level: Res<Level>,
+    mut query: Query<(&mut Transform, &HitBox, &mut Velocity, &Grapple)>,
+) {
+    for (mut transform, hitbox, mut vel, grapple) in query.iter_mut() {
+        let Some(anchor) = grapple.anchor else {
+            continue;
+        };
+        let offset = transform.translation.truncate() - anchor;
+        let distance = offset.length();
+        if distance <= grapple.length {
+            continue;
+        }
+        let outward = offset / distance;
+        let pos = transform.translation.truncate();
+        let pull = anchor + outward * grapple.length - pos;
+        let target = level
+            .box_cast(pos, hitbox.half_size, pull)
+            .map_or(pos + pull, |hit| hit.point);
+        transform.translation.x = target.x;
+        transform.translation.y = target.y;
+        let away = vel.0.dot(outward);
+        if away > 0. {
+            vel.0 -= outward * away;
+        }
+    }
+}
+
+fn draw_rope(
+    player: Query<(&Transform, &Grapple), Without<RopeMarker>>,
+    mut ropes: Query<(&mut Transform, &mut Sprite), With<RopeMarker>>,
+) {
+    for (transform, grapple) in player.iter() {
+        let (Some(anchor), Some(rope)) = (grapple.anchor, grapple.rope) else {
+            continue;
+        };
+        let Ok((mut rope_transform, mut sprite)) = ropes.get_mut(rope) else {
+            continue;
+        };
+        let pos = transform.translation.truncate();
+        let span = anchor - pos;
+        let mid = pos + span / 2.;
+        *rope_transform = Transform::from_translation(mid.extend(transform.translation.z))
+            .with_rotation(Quat::from_rotation_z(span.y.atan2(span.x)));
+        sprite.custom_size = Some(Vec2::new(span.length(), ROPE_WIDTH));
+    }
+}
+
+fn release_grapple_on_death(
+    mut commands: Commands,
+    mut query: Query<(&mut Movement, &mut Grapple)>,
+) {
+    for (mut movement, mut grapple) in query.iter_mut() {
+        detach_grapple(&mut commands, &mut movement, &mut grapple);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::physics::tests::{
+        hold, physics_world, player_pos, player_vel, press_jump, step, test_level,
+    };
+    use crate::physics::PhysicsConfig;
+
+    // open air over a floor with its top at y 16, one breakable block over x
+    // 48..64 and y 16..32
+    const ROOM: [&str; 8] = [
+        "........", "........", "........", "........", "........", "........", "...b....",
+        "########",
+    ];
+
+    fn world_with(ability: impl Bundle, pos: Vec2, vel: Vec2) -> World {
+        let mut world = physics_world(test_level(&ROOM), PhysicsConfig::default(), pos, vel);
+        let player = world
+            .query_filtered::<Entity, With<PlayerMarker>>()
+            .single(&world);
+        world.entity_mut(player).insert(ability);
+        world
+    }
+
+    fn step_abilities(world: &mut World, ticks: usize) {
+        for _ in 0..ticks {
+            world.run_system_once(start_ground_pound);
+            world.run_system_once(double_jump);
+            world.run_system_once(fire_grapple);
+            world.run_system_once(reel_grapple);
+            step(world, 1);
+            world.run_system_once(glide);
+            world.run_system_once(land_ground_pound);
+            world.run_system_once(swing_grapple);
+        }
+    }
+
+    fn movement(world: &mut World) -> &mut Movement {
+        world
+            .query::<&mut Movement>()
+            .single_mut(world)
+            .into_inner()
+    }
+
+    #[test]
+    fn double_jump_works_once_until_landing() {
+        let mut world = world_with(DoubleJump::default(), Vec2::new(88., 96.), Vec2::ZERO);
+        step_abilities(&mut world, 1);
+        press_jump(&mut world);
+        step_abilities(&mut world, 1);
+        assert!(player_vel(&mut world).y > 0., "no double jump");
+
+        step_abilities(&mut world, 30);
+        press_jump(&mut world);
+        step_abilities(&mut world, 1);
+        assert!(player_vel(&mut world).y < 0., "jumped twice in the air");
+
+        movement(&mut world).jump = false;
+        step_abilities(&mut world, 120);
+        assert!(!world.query::<&DoubleJump>().single(&world).used);
+    }
+
+    #[test]
+    fn glide_caps_the_fall_while_jump_is_held() {
+        let fall = Vec2::new(0., -200.);
+        let mut world = world_with(Glide, Vec2::new(24., 100.), fall);
+        movement(&mut world).jump_held = true;
+        step_abilities(&mut world, 1);
+        assert_eq!(player_vel(&mut world).y, -GLIDE_FALL_SPEED);
+
+        movement(&mut world).jump_held = false;
+        step_abilities(&mut world, 1);
+        assert!(player_vel(&mut world).y < -GLIDE_FALL_SPEED);
+    }
+
+    #[test]
+    fn ground_pound_breaks_the_block_it_lands_on() {
+        let mut world = world_with(GroundPound::default(), Vec2::new(56., 96.), Vec2::ZERO);
+        let block = world
+            .spawn((
+                Transform::from_translation(Vec3::new(56., 24., 0.)),
+                Breakable,
+            ))
+            .id();
+        hold(&mut world, Vec2::NEG_Y);
+        press_jump(&mut world);
+        step_abilities(&mut world, 1);
+        assert!(world.query::<&GroundPound>().single(&world).active);
+        assert_eq!(player_vel(&mut world), Vec2::new(0., -GROUND_POUND_SPEED));
+
+        step_abilities(&mut world, 20);
+        assert!(!world.query::<&GroundPound>().single(&world).active);
+        assert!(world.get_entity(block).is_none());
+        let level = world.resource::<Level>();
+        assert!(level.collider_at(Vec2::new(56., 24.)).is_none());
+        // the player drops through the gap onto the floor
+        hold(&mut world, Vec2::ZERO);
+        step_abilities(&mut world, 30);
+        let feet = player_pos(&mut world).y - PLAYER_HALF_SIZE.y;
+        assert!((16. ..17.).contains(&feet), "not on the floor : {}", feet);
+    }
+
+    fn grapple(world: &mut World) -> &Grapple {
+        world.query::<&Grapple>().single(world)
+    }
+
+    #[test]
+    fn grapple_hooks_the_ceiling_and_holds_the_rope_length() {
+        let level = test_level(&["########", "........", "........", "........", "........"]);
+        let mut world = physics_world(
+            level,
+            PhysicsConfig::default(),
+            Vec2::new(40., 24.),
+            Vec2::new(MAX_VEL_PLAYER, 0.),
+        );
+        let player = world
+            .query_filtered::<Entity, With<PlayerMarker>>()
+            .single(&world);
+        world.entity_mut(player).insert(Grapple::default());
+        hold(&mut world, Vec2::Y);
+        let input = movement(&mut world);
+        input.grapple = true;
+        input.grapple_held = true;
+        step_abilities(&mut world, 1);
+        assert_eq!(grapple(&mut world).anchor, Some(Vec2::new(40., 64.)));
+        assert!(movement(&mut world).swinging);
+
+        // swing for a while without reeling, the rope never stretches
+        hold(&mut world, Vec2::ZERO);
+        movement(&mut world).grapple = false;
+        for _ in 0..60 {
+            step_abilities(&mut world, 1);
+            let rope = grapple(&mut world).length;
+            let distance = player_pos(&mut world).distance(Vec2::new(40., 64.));
+            assert!(distance <= rope + 1e-3, "{} on a {} rope", distance, rope);
+        }
+
+        movement(&mut world).grapple_held = false;
+        step_abilities(&mut world, 1);
+        assert_eq!(grapple(&mut world).anchor, None);
+        assert!(!movement(&mut world).swinging);
+    }
+
+    #[test]
+    fn rope_pull_does_not_drag_the_hitbox_into_a_wall() {
+        // a block over x 32..48 and y 64..80 between the anchor and the player,
+        // whose box touches its bottom right corner
+        let level = test_level(&["........", "..#.....", "........", "........", "........"]);
+        let anchor = Vec2::new(24., 128.);
+        let mut world = physics_world(
+            level,
+            PhysicsConfig::default(),
+            Vec2::new(56., 56.),
+            Vec2::ZERO,
+        );
+        let player = world
+            .query_filtered::<Entity, With<PlayerMarker>>()
+            .single(&world);
+        world.entity_mut(player).insert(Grapple {
+            anchor: Some(anchor),
+            length: 60.,
+            rope: None,
+        });
+        world.run_system_once(swing_grapple);
+        let pos = player_pos(&mut world);
+        let level = world.resource::<Level>();
+        let overlapping = level.overlap_box(pos, PLAYER_HALF_SIZE);
+        assert!(
+            overlapping.iter().all(|item| !item.kind.is_solid()),
+            "pulled into the block at {:?}",
+            pos
+        );
+    }
+}
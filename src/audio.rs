@@ -0,0 +1,254 @@
+use std::time::Duration;
+
+use crate::level::Level;
+use crate::util::*;
+use bevy::audio::Volume;
+use bevy::prelude::*;
+use serde_json::*;
+
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SoundEvent>()
+            .init_resource::<AudioSettings>()
+            .add_systems(
+                Update,
+                (
+                    play_sound_events,
+                    update_sfx_lifetime,
+                    switch_level_music,
+                    crossfade_music.after(switch_level_music),
+                ),
+            );
+    }
+}
+
+#[derive(Event, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SoundEvent {
+    Jump,
+    Land,
+    Dash,
+    Death,
+    Pickup,
+}
+
+impl SoundEvent {
+    pub const ALL: [SoundEvent; 5] = [
+        SoundEvent::Jump,
+        SoundEvent::Land,
+        SoundEvent::Dash,
+        SoundEvent::Death,
+        SoundEvent::Pickup,
+    ];
+
+    pub fn file(&self) -> &'static str {
+        match self {
+            SoundEvent::Jump => "audio/jump.ogg",
+            SoundEvent::Land => "audio/land.ogg",
+            SoundEvent::Dash => "audio/dash.ogg",
+            SoundEvent::Death => "audio/death.ogg",
+            SoundEvent::Pickup => "audio/pickup.ogg",
+        }
+    }
+}
+
+// volume buses, the effective volume of a sound is master * bus
+#[derive(Resource, Clone, Copy, Debug, PartialEq)]
+pub struct AudioSettings {
+    pub master: f32,
+    pub music: f32,
+    pub sfx: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            master: 1.,
+            music: 0.6,
+            sfx: 0.8,
+        }
+    }
+}
+
+impl AudioSettings {
+    pub fn music_volume(&self) -> f32 {
+        self.master * self.music
+    }
+
+    pub fn sfx_volume(&self) -> f32 {
+        self.master * self.sfx
+    }
+
+    // stored under "audio" in the settings file, see save.rs
+    pub fn from_json(value: &Value) -> AudioSettings {
+        let mut settings = AudioSettings::default();
+        if let Some(v) = value["master"].as_f64() {
+            settings.master = (v as f32).clamp(0., 1.);
+        }
+        if let Some(v) = value["music"].as_f64() {
+            settings.music = (v as f32).clamp(0., 1.);
+        }
+        if let Some(v) = value["sfx"].as_f64() {
+            settings.sfx = (v as f32).clamp(0., 1.);
+        }
+        settings
+    }
+
+    pub fn to_json(self) -> Value {
+        json!({
+            "master": self.master,
+            "music": self.music,
+            "sfx": self.sfx,
+        })
+    }
+}
+
+// sfx entities are despawned after a fixed lifetime instead of relying on
+// PlaybackMode::Despawn, which never fires when there is no output device
+#[derive(Component)]
+pub struct SoundEffect(pub Timer);
+
+#[derive(Component)]
+pub struct MusicTrack {
+    pub file: String,
+    pub fade: f32,
+    pub target: f32,
+}
+
+fn play_sound_events(
+    mut commands: Commands,
+    mut events: EventReader<SoundEvent>,
+    settings: Res<AudioSettings>,
+    asset_server: Option<Res<AssetServer>>,
+) {
+    let Some(asset_server) = asset_server else {
+        events.clear();
+        return;
+    };
+    for event in events.read() {
+        commands.spawn((
+            AudioBundle {
+                source: asset_server.load(event.file()),
+                settings: PlaybackSettings::ONCE.with_volume(Volume::new(settings.sfx_volume())),
+            },
+            SoundEffect(Timer::new(
+                Duration::from_secs_f32(SFX_MAX_LIFETIME),
+                TimerMode::Once,
+            )),
+        ));
+    }
+}
+
+fn update_sfx_lifetime(
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut SoundEffect)>,
+    mut commands: Commands,
+) {
+    for (entity, mut sfx) in query.iter_mut() {
+        sfx.0.tick(time.delta());
+        if sfx.0.just_finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn switch_level_music(
+    mut commands: Commands,
+    level: Option<Res<Level>>,
+    mut query: Query<&mut MusicTrack>,
+    asset_server: Option<Res<AssetServer>>,
+) {
+    let Some(level) = level else {
+        return;
+    };
+    if !level.is_changed() {
+        return;
+    }
+    let mut already_playing = false;
+    for mut track in query.iter_mut() {
+        if Some(&track.file) == level.music_file.as_ref() {
+            track.target = 1.;
+            already_playing = true;
+        } else {
+            track.target = 0.;
+        }
+    }
+    let (Some(file), Some(asset_server)) = (&level.music_file, asset_server) else {
+        return;
+    };
+    if !already_playing {
+        commands.spawn((
+            AudioBundle {
+                source: asset_server.load(file),
+                settings: PlaybackSettings::LOOP.with_volume(Volume::ZERO),
+            },
+            MusicTrack {
+                file: file.clone(),
+                fade: 0.,
+                target: 1.,
+            },
+        ));
+    }
+}
+
+fn crossfade_music(
+    time: Res<Time>,
+    settings: Res<AudioSettings>,
+    mut query: Query<(Entity, &mut MusicTrack, Option<&AudioSink>)>,
+    mut commands: Commands,
+) {
+    let step = time.delta_seconds() / MUSIC_CROSSFADE_DURATION;
+    for (entity, mut track, sink) in query.iter_mut() {
+        if track.fade < track.target {
+            track.fade = (track.fade + step).min(track.target);
+        } else if track.fade > track.target {
+            track.fade = (track.fade - step).max(track.target);
+        }
+        if let Some(sink) = sink {
+            sink.set_volume(track.fade * settings.music_volume());
+        }
+        if track.target == 0. && track.fade == 0. {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buses_scale_with_master() {
+        let settings = AudioSettings {
+            master: 0.5,
+            music: 0.6,
+            sfx: 0.8,
+        };
+        assert_eq!(settings.music_volume(), 0.3);
+        assert_eq!(settings.sfx_volume(), 0.4);
+    }
+
+    #[test]
+    fn settings_are_clamped_and_round_trip() {
+        let settings = AudioSettings::from_json(&json!({ "master": 2., "sfx": -1. }));
+        assert_eq!(settings.master, 1.);
+        assert_eq!(settings.sfx, 0.);
+        assert_eq!(settings.music, AudioSettings::default().music);
+        assert_eq!(AudioSettings::from_json(&settings.to_json()), settings);
+    }
+
+    // no asset server and no output device, as in a headless test run
+    #[test]
+    fn sound_events_are_dropped_without_an_asset_server() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins).add_plugins(SoundPlugin);
+        app.world.send_event(SoundEvent::Jump);
+        app.update();
+        app.update();
+        assert_eq!(
+            app.world.query::<&SoundEffect>().iter(&app.world).count(),
+            0
+        );
+    }
+}
@@ -0,0 +1,383 @@
+use bevy::ecs::system::EntityCommands;
+use bevy::prelude::*;
+
+use crate::audio::SoundEvent;
+use crate::entities::{LdtkEntity, RegisterLdtkEntity};
+use crate::level::Level;
+use crate::physics::{
+    standing, wall_side, CollisionKind, DashState, Gravity, Grounded, Movement, PhysicsConfig,
+    PhysicsSet, Stamina, Velocity,
+};
+use crate::player::PlayerDeath;
+use crate::state::{on_level_start, GameState};
+use crate::util::*;
+
+pub struct ClimbingPlugin;
+
+const LADDER_COLOR: Color = Color::rgba(0.76, 0.6, 0.36, 0.8);
+const VINE_COLOR: Color = Color::rgba(0.3, 0.7, 0.3, 0.8);
+const EXHAUSTED_COLOR: Color = Color::rgb(1., 0.35, 0.35);
+// blinks per second of the low stamina warning
+const WARNING_BLINK_RATE: f32 = 8.;
+
+impl Plugin for ClimbingPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_ldtk_entity("Ladder", spawn_ladder)
+            .register_ldtk_entity("Vine", spawn_vine);
+        for schedule in on_level_start() {
+            app.add_systems(schedule, add_climbable_colliders);
+        }
+        app.add_systems(
+            Update,
+            (
+                (climb, wall_climb.after(climb)).in_set(PhysicsSet::PreStep),
+                (
+                    warn_low_stamina,
+                    let_go_on_death
+                        .run_if(on_event::<PlayerDeath>())
+                        .before(climb),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            ),
+        );
+    }
+}
+
+fn climbable_sprite(entity: &mut EntityCommands, item: &LdtkEntity, color: Color) {
+    entity.insert((
+        Sprite {
+            color,
+            custom_size: Some(item.size),
+            ..default()
+        },
+        Handle::<Image>::default(),
+    ));
+}
+
+fn spawn_ladder(entity: &mut EntityCommands, item: &LdtkEntity, _: &AssetServer) {
+    climbable_sprite(entity, item, LADDER_COLOR);
+}
+
+fn spawn_vine(entity: &mut EntityCommands, item: &LdtkEntity, _: &AssetServer) {
+    climbable_sprite(entity, item, VINE_COLOR);
+}
+
+// climbable entities join the IntGrid ones
+fn add_climbable_colliders(mut level: ResMut<Level>) {
+    level
+        .bypass_change_detection()
+        .add_entity_colliders(&["Ladder", "Vine"], CollisionKind::Climbable);
+}
+
+type ClimbQuery<'a> = (
+    &'a Transform,
+    &'a HitBox,
+    &'a Grounded,
+    &'a mut Velocity,
+    &'a mut Gravity,
+    &'a mut Movement,
+);
+
+// up or down on a climbable attaches, jump lets go sideways
+fn climb(
+    level: Res<Level>,
+    mut query: Query<ClimbQuery>,
+    mut sound_events: EventWriter<SoundEvent>,
+) {
+    for (transform, hitbox, grounded, mut vel, mut gravity, mut movement) in query.iter_mut() {
+        let center = transform.translation.truncate();
+        let on_climbable = level
+            .colliders
+            .iter()
+            .any(|item| item.kind == CollisionKind::Climbable && item.contains(center));
+        let on_ground = grounded.0 || standing(&level.colliders, transform.translation, *hitbox);
+        let dashing = !matches!(movement.dash.status, DashState::Ready);
+
+        if !movement.climbing {
+            let up_or_down =
+                movement.directional.y > 0.5 || (movement.directional.y < -0.5 && !on_ground);
+            if on_climbable && up_or_down && !dashing && !movement.crouching && !movement.swimming {
+                movement.climbing = true;
+                gravity.0 = 0.;
+                vel.0 = Vec2::ZERO;
+            }
+            continue;
+        }
+
+        if movement.jump {
+            let side = if movement.directional.x != 0. {
+                movement.directional.x.signum()
+            } else {
+                movement.facing
+            };
+            movement.jump = false;
+            vel.0 = Vec2::new(side * MAX_VEL_PLAYER, JMP_VEL_PLAYER * CLIMB_JUMP_HEIGHT);
+            sound_events.send(SoundEvent::Jump);
+        } else if on_climbable && !dashing && !(on_ground && movement.directional.y < 0.) {
+            vel.0 = Vec2::new(0., movement.directional.y * CLIMB_SPEED);
+            continue;
+        }
+        // jumped off, climbed past the end, reached the ground or dashed away
+        movement.climbing = false;
+        gravity.0 = GRAVITY_DEFAULT;
+    }
+}
+
+type WallClimbQuery<'a> = (
+    &'a Transform,
+    &'a HitBox,
+    &'a mut Velocity,
+    &'a mut Gravity,
+    &'a mut Movement,
+    &'a mut Stamina,
+);
+
+// grab holds onto a touching wall and up or down climbs it while stamina lasts,
+// once it runs out the player slips down instead
+fn wall_climb(
+    level: Res<Level>,
+    config: Res<PhysicsConfig>,
+    time: Res<Time>,
+    mut query: Query<WallClimbQuery>,
+    mut sound_events: EventWriter<SoundEvent>,
+) {
+    for (transform, hitbox, mut vel, mut gravity, mut movement, mut stamina) in query.iter_mut() {
+        // a grab reaches a few pixels further than a dash wall jump
+        let wall = wall_side(&level.colliders, transform.translation, *hitbox, GRAB_REACH);
+        let dashing = !matches!(movement.dash.status, DashState::Ready);
+        let holding = movement.grab_held
+            && wall.is_some()
+            && !dashing
+            && !movement.climbing
+            && !movement.crouching
+            && !movement.swimming;
+        let (Some(side), true) = (wall, holding && stamina.0 > 0.) else {
+            if movement.grabbing {
+                movement.grabbing = false;
+                gravity.0 = GRAVITY_DEFAULT;
+            }
+            if holding {
+                vel.0.y = vel.0.y.max(-WALL_SLIP_SPEED);
+            }
+            continue;
+        };
+
+        if movement.jump {
+            // kick off away from the wall
+            movement.jump = false;
+            movement.grabbing = false;
+            gravity.0 = GRAVITY_DEFAULT;
+            stamina.0 = (stamina.0 - STAMINA_JUMP_COST).max(0.);
+            vel.0 = Vec2::new(
+                -side * MAX_VEL_PLAYER * config.wall_jump_speed,
+                JMP_VEL_PLAYER * config.wall_jump_height,
+            );
+            sound_events.send(SoundEvent::Jump);
+            continue;
+        }
+
+        movement.grabbing = true;
+        gravity.0 = 0.;
+        let climb_y = movement.directional.y;
+        vel.0 = Vec2::new(0., climb_y * WALL_CLIMB_SPEED);
+        let cost = if climb_y > 0. { STAMINA_CLIMB_COST } else { 1. };
+        stamina.0 = (stamina.0 - cost * time.delta_seconds()).max(0.);
+    }
+}
+
+fn let_go_on_death(mut query: Query<(&mut Gravity, &mut Movement)>) {
+    for (mut gravity, mut movement) in query.iter_mut() {
+        if movement.climbing || movement.grabbing {
+            movement.climbing = false;
+            movement.grabbing = false;
+            gravity.0 = GRAVITY_DEFAULT;
+        }
+    }
+}
+
+// the player blinks red when stamina runs low and stays red when it is gone
+fn warn_low_stamina(time: Res<Time>, mut query: Query<(&Stamina, &mut Sprite)>) {
+    for (stamina, mut sprite) in query.iter_mut() {
+        let blink = (time.elapsed_seconds() * WARNING_BLINK_RATE).fract() < 0.5;
+        sprite.color = if stamina.0 <= 0. || (stamina.0 < STAMINA_WARNING && blink) {
+            EXHAUSTED_COLOR
+        } else {
+            Color::WHITE
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::physics::tests::{
+        hold, physics_world, player_pos, player_vel, press_jump, step, test_level,
+    };
+
+    // a ladder over x 32..48 from the floor top at y 16 up to y 64
+    const LADDER: [&str; 4] = ["..H..", "..H..", "..H..", "#####"];
+
+    fn step_climbing(world: &mut World, ticks: usize) {
+        for _ in 0..ticks {
+            world.run_system_once(climb);
+            world.run_system_once(wall_climb);
+            step(world, 1);
+        }
+    }
+
+    fn movement(world: &mut World) -> &Movement {
+        world.query::<&Movement>().single(world)
+    }
+
+    #[test]
+    fn pressing_up_on_a_ladder_attaches() {
+        let level = test_level(&LADDER);
+        let mut world = physics_world(
+            level,
+            PhysicsConfig::default(),
+            Vec2::new(40., 24.),
+            Vec2::ZERO,
+        );
+        step_climbing(&mut world, 2);
+        assert!(!movement(&mut world).climbing);
+        hold(&mut world, Vec2::Y);
+        step_climbing(&mut world, 10);
+        assert!(movement(&mut world).climbing);
+        assert!(player_pos(&mut world).y > 24.);
+        // no input holds still instead of falling
+        hold(&mut world, Vec2::ZERO);
+        step_climbing(&mut world, 1);
+        let y = player_pos(&mut world).y;
+        step_climbing(&mut world, 10);
+        assert_eq!(player_pos(&mut world).y, y);
+    }
+
+    #[test]
+    fn jump_lets_go_of_the_ladder_sideways() {
+        let level = test_level(&LADDER);
+        let mut world = physics_world(
+            level,
+            PhysicsConfig::default(),
+            Vec2::new(40., 24.),
+            Vec2::ZERO,
+        );
+        hold(&mut world, Vec2::Y);
+        step_climbing(&mut world, 10);
+        hold(&mut world, Vec2::NEG_X);
+        press_jump(&mut world);
+        step_climbing(&mut world, 1);
+        assert!(!movement(&mut world).climbing);
+        let vel = player_vel(&mut world);
+        assert!(vel.x < 0. && vel.y > 0., "did not jump off : {:?}", vel);
+    }
+
+    #[test]
+    fn death_lets_go_of_the_ladder() {
+        let level = test_level(&LADDER);
+        let mut world = physics_world(
+            level,
+            PhysicsConfig::default(),
+            Vec2::new(40., 24.),
+            Vec2::ZERO,
+        );
+        hold(&mut world, Vec2::Y);
+        step_climbing(&mut world, 10);
+        assert!(movement(&mut world).climbing);
+        world.run_system_once(let_go_on_death);
+        assert!(!movement(&mut world).climbing);
+        assert_eq!(world.query::<&Gravity>().single(&world).0, GRAVITY_DEFAULT);
+    }
+
+    // a wall over x 64..80 up to y 160, the player starts next to it in the air
+    const WALL: [&str; 10] = [
+        "....#", "....#", "....#", "....#", "....#", "....#", "....#", "....#", "....#", "#####",
+    ];
+
+    fn grab(world: &mut World, held: bool) {
+        let mut query = world.query::<&mut Movement>();
+        for mut movement in query.iter_mut(world) {
+            movement.grab_held = held;
+        }
+    }
+
+    fn stamina(world: &mut World) -> f32 {
+        world.query::<&Stamina>().single(world).0
+    }
+
+    #[test]
+    fn grab_holds_the_wall_until_stamina_runs_out() {
+        let level = test_level(&WALL);
+        let mut world = physics_world(
+            level,
+            PhysicsConfig::default(),
+            Vec2::new(54., 100.),
+            Vec2::ZERO,
+        );
+        grab(&mut world, true);
+        step_climbing(&mut world, 2);
+        assert!(movement(&mut world).grabbing);
+        let y = player_pos(&mut world).y;
+        step_climbing(&mut world, 60);
+        assert_eq!(player_pos(&mut world).y, y);
+        assert!(stamina(&mut world) < STAMINA_MAX);
+
+        // holding still costs one stamina a second, then the player slips
+        for _ in 0..(STAMINA_MAX * 60.) as usize {
+            if stamina(&mut world) == 0. {
+                break;
+            }
+            assert!(movement(&mut world).grabbing);
+            step_climbing(&mut world, 1);
+        }
+        assert_eq!(stamina(&mut world), 0.);
+        step_climbing(&mut world, 1);
+        assert!(!movement(&mut world).grabbing);
+        step_climbing(&mut world, 10);
+        let vel = player_vel(&mut world);
+        assert!(vel.y < 0., "not slipping : {:?}", vel);
+        assert!(vel.y > -WALL_SLIP_SPEED * 1.5, "falling freely : {:?}", vel);
+
+        // letting go drops to the floor, which refills the stamina
+        grab(&mut world, false);
+        step_climbing(&mut world, 60);
+        assert_eq!(stamina(&mut world), STAMINA_MAX);
+    }
+
+    #[test]
+    fn death_lets_go_of_the_wall() {
+        let level = test_level(&WALL);
+        let mut world = physics_world(
+            level,
+            PhysicsConfig::default(),
+            Vec2::new(56., 100.),
+            Vec2::ZERO,
+        );
+        grab(&mut world, true);
+        step_climbing(&mut world, 2);
+        assert!(movement(&mut world).grabbing);
+        world.run_system_once(let_go_on_death);
+        assert!(!movement(&mut world).grabbing);
+        assert_eq!(world.query::<&Gravity>().single(&world).0, GRAVITY_DEFAULT);
+    }
+
+    #[test]
+    fn jump_kicks_off_a_grabbed_wall() {
+        let level = test_level(&WALL);
+        let mut world = physics_world(
+            level,
+            PhysicsConfig::default(),
+            Vec2::new(56., 100.),
+            Vec2::ZERO,
+        );
+        grab(&mut world, true);
+        step_climbing(&mut world, 2);
+        press_jump(&mut world);
+        step_climbing(&mut world, 1);
+        assert!(!movement(&mut world).grabbing);
+        let vel = player_vel(&mut world);
+        assert!(vel.x < 0. && vel.y > 0., "did not kick off : {:?}", vel);
+        assert!(stamina(&mut world) < STAMINA_MAX);
+    }
+}
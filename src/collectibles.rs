@@ -0,0 +1,312 @@
+use std::time::Duration;
+
+use bevy::ecs::system::{EntityCommands, SystemParam};
+use bevy::prelude::*;
+
+use crate::audio::SoundEvent;
+use crate::entities::{LdtkEntity, RegisterLdtkEntity};
+use crate::level::{reset_level_stats, Level, LevelStats};
+use crate::physics::Grounded;
+use crate::player::{PlayerDeath, PlayerMarker, TrailParticle};
+use crate::save::{complete_level_save, SaveData};
+use crate::state::{on_level_start, GameState, LevelEntity};
+use crate::util::*;
+
+pub struct CollectiblesPlugin;
+
+const COIN_COLOR: Color = Color::rgb(1., 0.93, 0.35);
+const STRAWBERRY_COLOR: Color = Color::rgb(0.89, 0.23, 0.27);
+const EXIT_COLOR: Color = Color::rgba(0.17, 0.91, 0.96, 0.6);
+const COLLECTIBLE_SIZE: Vec2 = Vec2::splat(8.);
+// collectibles in front of the tiles, the exit behind them
+const COLLECTIBLE_Z: f32 = 0.5;
+const EXIT_Z: f32 = -0.5;
+const PICKUP_PARTICLE_COUNT: usize = 8;
+const PICKUP_PARTICLE_SPEED: f32 = 60.;
+// where a carried strawberry trails behind the player
+const FOLLOW_OFFSET: Vec2 = Vec2::new(-12., 12.);
+const FOLLOW_SPEED: f32 = 8.;
+
+impl Plugin for CollectiblesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CollectedItems>()
+            .register_ldtk_entity("Coin", spawn_coin)
+            .register_ldtk_entity("Strawberry", spawn_strawberry)
+            .register_ldtk_entity("LevelExit", spawn_level_exit);
+        for schedule in on_level_start() {
+            app.add_systems(
+                schedule,
+                (
+                    count_collectibles.after(reset_level_stats),
+                    reset_collected_items,
+                ),
+            );
+        }
+        app.add_systems(
+            Update,
+            (
+                pick_up_collectibles,
+                follow_player.after(pick_up_collectibles),
+                drop_on_death.run_if(on_event::<PlayerDeath>()),
+                reach_level_exit.after(follow_player),
+                move_pickup_particles,
+            )
+                .run_if(in_state(GameState::Playing)),
+        )
+        .add_systems(
+            OnEnter(GameState::LevelComplete),
+            save_collected_items.before(complete_level_save),
+        );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CollectibleKind {
+    Coin,
+    // only counts once the player lands safely while carrying it
+    Strawberry,
+}
+
+#[derive(Component)]
+pub struct Collectible {
+    pub iid: String,
+    pub kind: CollectibleKind,
+    pub origin: Vec2,
+    // how long a strawberry must stay grounded before it counts
+    pub safe_time: f32,
+}
+
+// strawberry picked up but not yet secured
+#[derive(Component)]
+pub struct Following(pub Timer);
+
+#[derive(Component)]
+pub struct LevelExitMarker;
+
+#[derive(Component)]
+pub struct PickupParticle(pub Vec2);
+
+// iids collected during the current run, written to the save on completion
+#[derive(Resource, Default)]
+pub struct CollectedItems(pub Vec<String>);
+
+fn overlaps(a: Vec2, b: Vec2) -> bool {
+    let delta = (a - b).abs();
+    delta.x < BLOCK_SIZE.x && delta.y < BLOCK_SIZE.y
+}
+
+fn reset_collected_items(mut collected: ResMut<CollectedItems>) {
+    collected.0.clear();
+}
+
+fn collectible_sprite(color: Color, size: Vec2) -> (Sprite, Handle<Image>) {
+    let sprite = Sprite {
+        color,
+        custom_size: Some(size),
+        ..default()
+    };
+    (sprite, Handle::default())
+}
+
+fn spawn_collectible(entity: &mut EntityCommands, item: &LdtkEntity, kind: CollectibleKind) {
+    let color = match kind {
+        CollectibleKind::Coin => COIN_COLOR,
+        CollectibleKind::Strawberry => STRAWBERRY_COLOR,
+    };
+    entity.insert((
+        collectible_sprite(color, COLLECTIBLE_SIZE),
+        Transform::from_translation(item.pos.extend(COLLECTIBLE_Z)),
+        Collectible {
+            iid: item.iid.clone(),
+            kind,
+            origin: item.pos,
+            safe_time: item
+                .fields
+                .float("SafeTime")
+                .unwrap_or(STRAWBERRY_SAFE_TIME),
+        },
+    ));
+}
+
+fn spawn_coin(entity: &mut EntityCommands, item: &LdtkEntity, _: &AssetServer) {
+    spawn_collectible(entity, item, CollectibleKind::Coin);
+}
+
+fn spawn_strawberry(entity: &mut EntityCommands, item: &LdtkEntity, _: &AssetServer) {
+    spawn_collectible(entity, item, CollectibleKind::Strawberry);
+}
+
+fn spawn_level_exit(entity: &mut EntityCommands, item: &LdtkEntity, _: &AssetServer) {
+    entity.insert((
+        collectible_sprite(EXIT_COLOR, BLOCK_SIZE),
+        Transform::from_translation(item.pos.extend(EXIT_Z)),
+        LevelExitMarker,
+    ));
+}
+
+fn count_collectibles(mut stats: ResMut<LevelStats>, level: Res<Level>) {
+    let count = |identifier: &str| {
+        level
+            .entities
+            .iter()
+            .filter(|item| item.identifier == identifier)
+            .count()
+    };
+    stats.total_collectibles = count("Coin");
+    stats.total_strawberries = count("Strawberry");
+}
+
+fn spawn_pickup_effect(commands: &mut Commands, pos: Vec2, color: Color) {
+    for i in 0..PICKUP_PARTICLE_COUNT {
+        let angle = i as f32 / PICKUP_PARTICLE_COUNT as f32 * std::f32::consts::TAU;
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(Vec2::splat(2.)),
+                    ..default()
+                },
+                transform: Transform::from_translation(pos.extend(1.)),
+                ..default()
+            },
+            PickupParticle(Vec2::from_angle(angle) * PICKUP_PARTICLE_SPEED),
+            TrailParticle(Timer::new(Duration::from_secs_f32(0.4), TimerMode::Once)),
+            LevelEntity,
+        ));
+    }
+}
+
+fn move_pickup_particles(time: Res<Time>, mut query: Query<(&PickupParticle, &mut Transform)>) {
+    for (particle, mut transform) in query.iter_mut() {
+        transform.translation += (particle.0 * time.delta_seconds()).extend(0.);
+    }
+}
+
+// everything touched when an item counts, shared by pickup, landing and exit
+#[derive(SystemParam)]
+struct Pickups<'w, 's> {
+    commands: Commands<'w, 's>,
+    stats: ResMut<'w, LevelStats>,
+    collected: ResMut<'w, CollectedItems>,
+    sound_events: EventWriter<'w, SoundEvent>,
+}
+
+impl Pickups<'_, '_> {
+    fn collect(&mut self, entity: Entity, item: &Collectible, pos: Vec2) {
+        let color = match item.kind {
+            CollectibleKind::Coin => {
+                self.stats.collected += 1;
+                COIN_COLOR
+            }
+            CollectibleKind::Strawberry => {
+                self.stats.strawberries += 1;
+                STRAWBERRY_COLOR
+            }
+        };
+        self.collected.0.push(item.iid.clone());
+        self.sound_events.send(SoundEvent::Pickup);
+        spawn_pickup_effect(&mut self.commands, pos, color);
+        self.commands.entity(entity).despawn();
+    }
+}
+
+fn pick_up_collectibles(
+    player: Query<&Transform, With<PlayerMarker>>,
+    query: Query<(Entity, &Collectible, &Transform), Without<Following>>,
+    mut pickups: Pickups,
+) {
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    for (entity, item, transform) in query.iter() {
+        let pos = transform.translation.truncate();
+        if !overlaps(player.translation.truncate(), pos) {
+            continue;
+        }
+        match item.kind {
+            CollectibleKind::Coin => pickups.collect(entity, item, pos),
+            CollectibleKind::Strawberry => {
+                pickups
+                    .commands
+                    .entity(entity)
+                    .insert(Following(Timer::from_seconds(
+                        item.safe_time,
+                        TimerMode::Once,
+                    )));
+            }
+        }
+    }
+}
+
+// carried strawberries trail the player and are secured after standing on
+// the ground for a moment
+fn follow_player(
+    time: Res<Time>,
+    player: Query<(&Transform, &Grounded), With<PlayerMarker>>,
+    mut query: Query<(Entity, &Collectible, &mut Following, &mut Transform), Without<PlayerMarker>>,
+    mut pickups: Pickups,
+) {
+    let Ok((player, grounded)) = player.get_single() else {
+        return;
+    };
+    let target = player.translation.truncate() + FOLLOW_OFFSET;
+    for (entity, item, mut following, mut transform) in query.iter_mut() {
+        let pos = transform
+            .translation
+            .truncate()
+            .lerp(target, (FOLLOW_SPEED * time.delta_seconds()).min(1.));
+        transform.translation.x = pos.x;
+        transform.translation.y = pos.y;
+        if !grounded.0 {
+            following.0.reset();
+            continue;
+        }
+        if following.0.tick(time.delta()).finished() {
+            pickups.collect(entity, item, pos);
+        }
+    }
+}
+
+fn drop_on_death(
+    mut commands: Commands,
+    mut query: Query<(Entity, &Collectible, &mut Transform), With<Following>>,
+) {
+    for (entity, item, mut transform) in query.iter_mut() {
+        transform.translation.x = item.origin.x;
+        transform.translation.y = item.origin.y;
+        commands.entity(entity).remove::<Following>();
+    }
+}
+
+// reaching the exit also secures anything still being carried
+fn reach_level_exit(
+    player: Query<&Transform, With<PlayerMarker>>,
+    exit: Query<&Transform, With<LevelExitMarker>>,
+    query: Query<(Entity, &Collectible, &Transform), With<Following>>,
+    mut pickups: Pickups,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let (Ok(player), Ok(exit)) = (player.get_single(), exit.get_single()) else {
+        return;
+    };
+    if !overlaps(player.translation.truncate(), exit.translation.truncate()) {
+        return;
+    }
+    for (entity, item, transform) in query.iter() {
+        pickups.collect(entity, item, transform.translation.truncate());
+    }
+    next_state.set(GameState::LevelComplete);
+}
+
+fn save_collected_items(
+    collected: Res<CollectedItems>,
+    level: Res<Level>,
+    mut save: ResMut<SaveData>,
+) {
+    let found = save.collectibles.entry(level.name.clone()).or_default();
+    for iid in &collected.0 {
+        if !found.contains(iid) {
+            found.push(iid.clone());
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use bevy::prelude::*;
+
+use crate::entities::EntityIid;
+use crate::level::Level;
+use crate::physics::*;
+use crate::player::PlayerMarker;
+use crate::state::GameState;
+use crate::util::*;
+
+pub struct DebugPlugin;
+
+// vectors are scaled down so they fit on screen next to the player
+const VELOCITY_DRAW_SCALE: f32 = 0.25;
+const ACCELERATION_DRAW_SCALE: f32 = 0.05;
+
+#[derive(Resource, Default)]
+pub struct DebugOverlay {
+    pub enabled: bool,
+}
+
+#[derive(Component)]
+pub struct DebugPanelMarker;
+
+impl Plugin for DebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebugOverlay>()
+            .add_systems(Startup, spawn_debug_panel)
+            .add_systems(
+                Update,
+                (
+                    toggle_debug_overlay,
+                    (
+                        draw_level_bounds,
+                        draw_entity_debug,
+                        draw_player_debug,
+                        update_debug_panel,
+                    )
+                        .run_if(debug_overlay_enabled)
+                        .run_if(in_state(GameState::Playing).or_else(in_state(GameState::Paused))),
+                ),
+            );
+    }
+}
+
+fn debug_overlay_enabled(overlay: Res<DebugOverlay>) -> bool {
+    overlay.enabled
+}
+
+fn toggle_debug_overlay(
+    input: Res<ButtonInput<KeyCode>>,
+    mut overlay: ResMut<DebugOverlay>,
+    mut query: Query<&mut Visibility, With<DebugPanelMarker>>,
+) {
+    if !input.just_pressed(KeyCode::F1) {
+        return;
+    }
+    overlay.enabled = !overlay.enabled;
+    for mut visibility in query.iter_mut() {
+        *visibility = if overlay.enabled {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+fn spawn_debug_panel(mut commands: Commands) {
+    let mut bundle = TextBundle::from_section(
+        "",
+        TextStyle {
+            font_size: 16.,
+            color: Color::YELLOW,
+            ..default()
+        },
+    )
+    .with_style(Style {
+        position_type: PositionType::Absolute,
+        top: Val::Px(5.),
+        right: Val::Px(5.),
+        ..default()
+    });
+    bundle.visibility = Visibility::Hidden;
+    commands.spawn((bundle, DebugPanelMarker));
+}
+
+fn draw_level_bounds(mut gizmos: Gizmos, level: Res<Level>) {
+    for item in &level.colliders {
+        let color = match item.kind {
+            CollisionKind::Solid => Color::GREEN,
+            CollisionKind::OneWay => Color::YELLOW_GREEN,
+            CollisionKind::Hazard => Color::RED,
+            CollisionKind::Water => Color::BLUE,
+            CollisionKind::Ice => Color::TURQUOISE,
+            CollisionKind::Breakable => Color::ORANGE,
+            CollisionKind::Climbable => Color::OLIVE,
+        };
+        gizmos.rect_2d(item.pos, 0., item.bounds.half_size * 2., color);
+    }
+}
+
+// LDtk entity bounds, with their point fields and entity references
+fn draw_entity_debug(
+    mut gizmos: Gizmos,
+    level: Res<Level>,
+    query: Query<(&EntityIid, &Transform)>,
+) {
+    for item in &level.entities {
+        gizmos.rect_2d(item.pos, 0., item.size, Color::PURPLE);
+        for point in item.fields.points() {
+            gizmos.line_2d(item.pos, point, Color::PURPLE);
+            gizmos.circle_2d(point, 2., Color::PURPLE);
+        }
+        for iid in item.fields.entity_refs() {
+            // refs follow the spawned entity, it may have moved since loading
+            if let Some((_, target)) = query.iter().find(|(other, _)| other.0 == iid) {
+                gizmos.arrow_2d(item.pos, target.translation.truncate(), Color::PINK);
+            }
+        }
+    }
+}
+
+fn draw_player_debug(
+    mut gizmos: Gizmos,
+    level: Res<Level>,
+    query: Query<(&Transform, &HitBox, &Velocity, &Acceleration, &Movement), With<PlayerMarker>>,
+    time: Res<Time>,
+) {
+    for (transform, hitbox, vel, accel, movement) in query.iter() {
+        let pos = transform.translation.truncate();
+        gizmos.rect_2d(pos, 0., hitbox.half_size * 2., Color::CYAN);
+
+        // estimated probes for the next step, the real ones also include acceleration
+        let step = vel.0 * time.delta_seconds();
+        let probe = probe_x(transform.translation, *hitbox, step.x);
+        gizmos.circle_2d(probe, 1., Color::RED);
+        let probe = probe_y(transform.translation, *hitbox, step.y);
+        gizmos.circle_2d(probe, 1., Color::RED);
+
+        gizmos.arrow_2d(pos, pos + vel.0 * VELOCITY_DRAW_SCALE, Color::BLUE);
+        gizmos.arrow_2d(pos, pos + accel.0 * ACCELERATION_DRAW_SCALE, Color::ORANGE);
+
+        // where the hitbox would stop along the drawn velocity
+        let motion = vel.0 * VELOCITY_DRAW_SCALE;
+        if let Some(hit) = level.box_cast(pos, hitbox.half_size, motion) {
+            gizmos.rect_2d(hit.point, 0., hitbox.half_size * 2., Color::WHITE);
+            gizmos.line_2d(hit.point, hit.point + hit.normal * 8., Color::WHITE);
+        }
+
+        if matches!(movement.dash.status, DashState::Dashing) {
+            gizmos.circle_2d(movement.dash.start_point.truncate(), 3., Color::WHITE);
+        }
+    }
+}
+
+fn update_debug_panel(
+    query: Query<(&Transform, &HitBox, &Grounded, &Movement), With<PlayerMarker>>,
+    mut query_text: Query<&mut Text, With<DebugPanelMarker>>,
+    level: Res<Level>,
+) {
+    let (Ok((transform, hitbox, grounded, movement)), Ok(mut text)) =
+        (query.get_single(), query_text.get_single_mut())
+    else {
+        return;
+    };
+    text.sections[0].value = format!(
+        "grounded : {}\ndash_state : {:?}\ndash_count : {}\ndash_distance : {}",
+        grounded.0, movement.dash.status, movement.dash.dash_count, movement.dash.distance
+    );
+    let pos = transform.translation.truncate();
+    let overlapping: Vec<CollisionKind> = level
+        .overlap_box(pos, hitbox.half_size)
+        .iter()
+        .map(|item| item.kind)
+        .collect();
+    text.sections[0].value += &format!("\noverlapping : {:?}", overlapping);
+    // fields of the LDtk entity the player is standing in
+    let touched = level.entities.iter().find(|item| {
+        item.identifier != "PlayerSpawnLocation"
+            && Rect::from_center_size(item.pos, item.size).contains(pos)
+    });
+    if let Some(item) = touched {
+        text.sections[0].value += &format!("\n{}", item.identifier);
+        for (name, value) in &item.fields.0 {
+            text.sections[0].value += &format!("\n  {} : {}", name, value);
+        }
+    }
+}
@@ -0,0 +1,410 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use bevy::ecs::system::EntityCommands;
+use bevy::prelude::*;
+use serde_json::Value;
+
+use crate::level::{Level, LevelLoadError};
+use crate::state::{on_level_start, LevelEntity};
+
+pub struct EntitiesPlugin;
+
+// builds the bundle for one LDtk entity instance, the entity already has its
+// Transform, EntityIid and EntityFields
+pub type SpawnFn = fn(&mut EntityCommands, &LdtkEntity, &AssetServer);
+
+impl Plugin for EntitiesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EntityRegistry>();
+        for schedule in on_level_start() {
+            app.add_systems(schedule, spawn_level_entities);
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct EntityRegistry {
+    spawners: HashMap<String, SpawnFn>,
+}
+
+pub trait RegisterLdtkEntity {
+    fn register_ldtk_entity(&mut self, identifier: &str, spawn: SpawnFn) -> &mut Self;
+}
+
+impl RegisterLdtkEntity for App {
+    fn register_ldtk_entity(&mut self, identifier: &str, spawn: SpawnFn) -> &mut Self {
+        // plugins may register before EntitiesPlugin is added
+        self.world
+            .get_resource_or_insert_with(EntityRegistry::default)
+            .spawners
+            .insert(identifier.to_string(), spawn);
+        self
+    }
+}
+
+// typed copy of an LDtk field instance, points are converted to world space
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldValue {
+    Null,
+    Int(i64),
+    Float(f32),
+    Bool(bool),
+    String(String),
+    Enum(String),
+    Color(Color),
+    Point(Vec2),
+    EntityRef(String),
+    Array(Vec<FieldValue>),
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FieldValue::Null => write!(f, "null"),
+            FieldValue::Int(value) => write!(f, "{}", value),
+            FieldValue::Float(value) => write!(f, "{:.2}", value),
+            FieldValue::Bool(value) => write!(f, "{}", value),
+            FieldValue::String(value) => write!(f, "\"{}\"", value),
+            FieldValue::Enum(value) => write!(f, "{}", value),
+            FieldValue::Color(value) => write!(f, "{:?}", value.as_rgba_f32()),
+            FieldValue::Point(value) => write!(f, "({}, {})", value.x, value.y),
+            FieldValue::EntityRef(iid) => write!(f, "-> {}", iid),
+            FieldValue::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+#[derive(Component, Clone, Debug, Default, PartialEq)]
+pub struct EntityFields(pub HashMap<String, FieldValue>);
+
+impl EntityFields {
+    pub fn float(&self, name: &str) -> Option<f32> {
+        match self.0.get(name)? {
+            FieldValue::Float(value) => Some(*value),
+            FieldValue::Int(value) => Some(*value as f32),
+            _ => None,
+        }
+    }
+
+    // every point in the fields, including the ones inside arrays
+    pub fn points(&self) -> Vec<Vec2> {
+        let mut points = Vec::new();
+        for value in self.0.values() {
+            collect_fields(value, &mut |item| {
+                if let FieldValue::Point(point) = item {
+                    points.push(*point);
+                }
+            });
+        }
+        points
+    }
+
+    pub fn entity_refs(&self) -> Vec<&str> {
+        let mut refs = Vec::new();
+        for value in self.0.values() {
+            collect_fields(value, &mut |item| {
+                if let FieldValue::EntityRef(iid) = item {
+                    refs.push(iid.as_str());
+                }
+            });
+        }
+        refs
+    }
+}
+
+fn collect_fields<'a>(value: &'a FieldValue, visit: &mut impl FnMut(&'a FieldValue)) {
+    match value {
+        FieldValue::Array(items) => {
+            for item in items {
+                collect_fields(item, visit);
+            }
+        }
+        _ => visit(value),
+    }
+}
+
+#[derive(Component, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EntityIid(pub String);
+
+// one entity instance from the Entities layer
+#[derive(Clone, Debug, PartialEq)]
+pub struct LdtkEntity {
+    pub identifier: String,
+    pub iid: String,
+    // center of the entity in world space
+    pub pos: Vec2,
+    pub size: Vec2,
+    pub fields: EntityFields,
+}
+
+impl LdtkEntity {
+    pub fn from_json(
+        value: &Value,
+        world_height: f32,
+        grid_size: f32,
+    ) -> Result<LdtkEntity, LevelLoadError> {
+        let field = |name: &'static str| {
+            value[name]
+                .as_f64()
+                .map(|value| value as f32)
+                .ok_or(LevelLoadError::MissingField(name))
+        };
+        let size = Vec2::new(field("width")?, field("height")?);
+        let px = &value["px"];
+        let (Some(x), Some(y)) = (px[0].as_f64(), px[1].as_f64()) else {
+            return Err(LevelLoadError::MissingField("entityInstances.px"));
+        };
+        // px is where the pivot sits, a fraction of the size from the top left
+        let pivot = &value["__pivot"];
+        let pivot = Vec2::new(
+            pivot[0].as_f64().unwrap_or(0.) as f32,
+            pivot[1].as_f64().unwrap_or(0.) as f32,
+        );
+        let top_left = Vec2::new(x as f32, y as f32) - pivot * size;
+        // entities share the tile mapping, centered on the cells they cover
+        let pos = Vec2::new(
+            top_left.x + size.x / 2.,
+            world_height - top_left.y + grid_size - size.y / 2.,
+        );
+
+        let mut fields = EntityFields::default();
+        for instance in value["fieldInstances"].as_array().into_iter().flatten() {
+            let Some(name) = instance["__identifier"].as_str() else {
+                continue;
+            };
+            let ty = instance["__type"].as_str().unwrap_or_default();
+            let to_world = |cx: f64, cy: f64| {
+                Vec2::new(cx as f32 * grid_size, world_height - cy as f32 * grid_size)
+                    + grid_size / 2.
+            };
+            fields.0.insert(
+                name.to_string(),
+                parse_field(ty, &instance["__value"], &to_world),
+            );
+        }
+
+        Ok(LdtkEntity {
+            identifier: value["__identifier"]
+                .as_str()
+                .ok_or(LevelLoadError::MissingField("__identifier"))?
+                .to_string(),
+            iid: value["iid"].as_str().unwrap_or_default().to_string(),
+            pos,
+            size,
+            fields,
+        })
+    }
+}
+
+fn parse_field(ty: &str, value: &Value, to_world: &impl Fn(f64, f64) -> Vec2) -> FieldValue {
+    if value.is_null() {
+        return FieldValue::Null;
+    }
+    if let Some(inner) = ty
+        .strip_prefix("Array<")
+        .and_then(|ty| ty.strip_suffix('>'))
+    {
+        let items = value.as_array().into_iter().flatten();
+        return FieldValue::Array(
+            items
+                .map(|item| parse_field(inner, item, to_world))
+                .collect(),
+        );
+    }
+    let parsed = match ty {
+        "Int" => value.as_i64().map(FieldValue::Int),
+        "Float" => value.as_f64().map(|value| FieldValue::Float(value as f32)),
+        "Bool" => value.as_bool().map(FieldValue::Bool),
+        "String" | "Multilines" | "FilePath" => value
+            .as_str()
+            .map(|value| FieldValue::String(value.to_string())),
+        "Color" => value
+            .as_str()
+            .and_then(|value| Color::hex(value).ok())
+            .map(FieldValue::Color),
+        "Point" => match (value["cx"].as_f64(), value["cy"].as_f64()) {
+            (Some(cx), Some(cy)) => Some(FieldValue::Point(to_world(cx, cy))),
+            _ => None,
+        },
+        "EntityRef" => value["entityIid"]
+            .as_str()
+            .map(|iid| FieldValue::EntityRef(iid.to_string())),
+        // "LocalEnum.Name" and "ExternEnum.Name"
+        _ if ty.contains("Enum.") => value
+            .as_str()
+            .map(|value| FieldValue::Enum(value.to_string())),
+        _ => None,
+    };
+    parsed.unwrap_or_else(|| {
+        warn!("unsupported LDtk field {} : {}", ty, value);
+        FieldValue::Null
+    })
+}
+
+fn spawn_level_entities(
+    mut commands: Commands,
+    level: Res<Level>,
+    registry: Res<EntityRegistry>,
+    asset_server: Res<AssetServer>,
+) {
+    for item in &level.entities {
+        let Some(spawn) = registry.spawners.get(&item.identifier) else {
+            warn!(
+                "no spawn function registered for LDtk entity {}",
+                item.identifier
+            );
+            continue;
+        };
+        let mut entity = commands.spawn((
+            SpatialBundle::from_transform(Transform::from_translation(
+                item.pos.extend(level.world_pos.z),
+            )),
+            EntityIid(item.iid.clone()),
+            item.fields.clone(),
+            LevelEntity,
+        ));
+        spawn(&mut entity, item, &asset_server);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    // a 16x16 entity at px (16, 32) in a level 64 pixels high
+    fn entity(fields: Value) -> LdtkEntity {
+        let value = json!({
+            "__identifier": "Test",
+            "iid": "test-iid",
+            "width": 16,
+            "height": 16,
+            "px": [16, 32],
+            "fieldInstances": fields,
+        });
+        LdtkEntity::from_json(&value, 64., 16.).unwrap()
+    }
+
+    fn field(ty: &str, value: Value) -> FieldValue {
+        let item = entity(json!([{ "__identifier": "F", "__type": ty, "__value": value }]));
+        item.fields.0["F"].clone()
+    }
+
+    #[test]
+    fn entity_is_centered_on_its_cells() {
+        let item = entity(json!([]));
+        assert_eq!(item.identifier, "Test");
+        assert_eq!(item.iid, "test-iid");
+        assert_eq!(item.pos, Vec2::new(24., 40.));
+        assert_eq!(item.size, Vec2::splat(16.));
+    }
+
+    #[test]
+    fn tall_entity_covers_the_cells_below_its_top() {
+        // 16x48 from px y 16 down to the bottom of the level, the cells whose
+        // tiles are centered at y 56, 40 and 24
+        let value = json!({
+            "__identifier": "Ladder",
+            "width": 16,
+            "height": 48,
+            "px": [16, 16],
+        });
+        let item = LdtkEntity::from_json(&value, 64., 16.).unwrap();
+        assert_eq!(item.pos, Vec2::new(24., 40.));
+        // the same cells with the pivot at the bottom center
+        let value = json!({
+            "__identifier": "Ladder",
+            "width": 16,
+            "height": 48,
+            "px": [24, 64],
+            "__pivot": [0.5, 1],
+        });
+        let item = LdtkEntity::from_json(&value, 64., 16.).unwrap();
+        assert_eq!(item.pos, Vec2::new(24., 40.));
+    }
+
+    #[test]
+    fn missing_position_is_an_error() {
+        let value = json!({ "__identifier": "Test", "width": 16, "height": 16 });
+        assert!(LdtkEntity::from_json(&value, 64., 16.).is_err());
+    }
+
+    #[test]
+    fn scalar_fields_are_typed() {
+        assert_eq!(field("Int", json!(3)), FieldValue::Int(3));
+        assert_eq!(field("Float", json!(1.5)), FieldValue::Float(1.5));
+        assert_eq!(field("Bool", json!(true)), FieldValue::Bool(true));
+        assert_eq!(
+            field("Multilines", json!("a\nb")),
+            FieldValue::String("a\nb".to_string())
+        );
+        assert_eq!(
+            field("LocalEnum.Ability", json!("Glide")),
+            FieldValue::Enum("Glide".to_string())
+        );
+        assert_eq!(
+            field("Color", json!("#FF0000")),
+            FieldValue::Color(Color::rgb(1., 0., 0.))
+        );
+        assert_eq!(field("Int", Value::Null), FieldValue::Null);
+        // wrong json type or an unknown field type
+        assert_eq!(field("Int", json!("three")), FieldValue::Null);
+        assert_eq!(field("Tile", json!({})), FieldValue::Null);
+    }
+
+    #[test]
+    fn points_and_refs_are_converted() {
+        assert_eq!(
+            field("Point", json!({ "cx": 2, "cy": 1 })),
+            FieldValue::Point(Vec2::new(40., 56.))
+        );
+        assert_eq!(
+            field(
+                "EntityRef",
+                json!({ "entityIid": "other", "layerIid": "layer" })
+            ),
+            FieldValue::EntityRef("other".to_string())
+        );
+    }
+
+    #[test]
+    fn arrays_are_parsed_item_by_item() {
+        let item = entity(json!([
+            {
+                "__identifier": "Path",
+                "__type": "Array<Point>",
+                "__value": [{ "cx": 0, "cy": 0 }, { "cx": 1, "cy": 3 }],
+            },
+            {
+                "__identifier": "Targets",
+                "__type": "Array<EntityRef>",
+                "__value": [{ "entityIid": "a" }, { "entityIid": "b" }],
+            },
+            { "__identifier": "Speed", "__type": "Int", "__value": 2 },
+        ]));
+        assert_eq!(
+            item.fields.0["Path"],
+            FieldValue::Array(vec![
+                FieldValue::Point(Vec2::new(8., 72.)),
+                FieldValue::Point(Vec2::new(24., 24.)),
+            ])
+        );
+        assert_eq!(item.fields.points().len(), 2);
+        let mut refs = item.fields.entity_refs();
+        refs.sort();
+        assert_eq!(refs, vec!["a", "b"]);
+        assert_eq!(item.fields.float("Speed"), Some(2.));
+        assert_eq!(item.fields.float("Path"), None);
+    }
+}
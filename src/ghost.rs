@@ -0,0 +1,148 @@
+use std::fs;
+
+use bevy::prelude::*;
+use serde_json::{json, Value};
+
+use crate::level::Level;
+use crate::physics::{DashState, Movement};
+use crate::player::{afterimage_bundle, PlayerMarker};
+use crate::save::write_atomic;
+use crate::speedrun::SpeedrunTimer;
+use crate::state::{on_level_start, GameState, LevelEntity};
+use crate::util::*;
+
+pub struct GhostPlugin;
+
+impl Plugin for GhostPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GhostRecorder>();
+        for schedule in on_level_start() {
+            app.add_systems(schedule, (reset_ghost_recorder, spawn_ghost));
+        }
+        app.add_systems(
+            FixedUpdate,
+            (record_ghost_frame, play_ghost_frame).run_if(in_state(GameState::Playing)),
+        )
+        .add_systems(OnEnter(GameState::LevelComplete), save_best_ghost);
+    }
+}
+
+// one fixed tick of the player, enough to redraw it as a ghost
+#[derive(Clone, Copy)]
+pub struct GhostFrame {
+    pub position: Vec2,
+    pub dashing: bool,
+}
+
+#[derive(Resource, Default)]
+pub struct GhostRecorder {
+    pub frames: Vec<GhostFrame>,
+}
+
+#[derive(Component)]
+pub struct Ghost {
+    pub frames: Vec<GhostFrame>,
+    pub index: usize,
+}
+
+pub fn ghost_file(level_name: &str) -> String {
+    format!("{}/{}.json", GHOSTS_DIR, level_name)
+}
+
+pub fn load_ghost(filepath: &str) -> Option<Vec<GhostFrame>> {
+    let buffer = fs::read_to_string(filepath).ok()?;
+    let value: Value = serde_json::from_str(buffer.as_str()).ok()?;
+    let frames = value["frames"]
+        .as_array()?
+        .iter()
+        .filter_map(|frame| {
+            Some(GhostFrame {
+                position: Vec2::new(frame[0].as_f64()? as f32, frame[1].as_f64()? as f32),
+                dashing: frame[2].as_bool()?,
+            })
+        })
+        .collect();
+    Some(frames)
+}
+
+pub fn save_ghost(filepath: &str, frames: &[GhostFrame]) {
+    let frames: Vec<Value> = frames
+        .iter()
+        .map(|frame| json!([frame.position.x, frame.position.y, frame.dashing]))
+        .collect();
+    let value = json!({ "ticks": frames.len(), "frames": frames });
+    if let Err(err) = write_atomic(filepath, &value.to_string()) {
+        warn!("could not save ghost to {} : {}", filepath, err);
+    }
+}
+
+fn reset_ghost_recorder(mut recorder: ResMut<GhostRecorder>) {
+    recorder.frames.clear();
+}
+
+fn spawn_ghost(mut commands: Commands, asset_server: Res<AssetServer>, level: Res<Level>) {
+    let Some(frames) = load_ghost(&ghost_file(&level.name)) else {
+        return;
+    };
+    let Some(first) = frames.first() else {
+        return;
+    };
+    let mut bundle = afterimage_bundle(
+        &asset_server,
+        Transform::from_translation(first.position.extend(-0.1)),
+    );
+    bundle.sprite.color = bundle.sprite.color.with_a(GHOST_ALPHA);
+    commands.spawn((bundle, Ghost { frames, index: 0 }, LevelEntity));
+}
+
+// frames are only recorded while the speedrun clock runs so the ghost starts
+// on the same tick as the player
+fn record_ghost_frame(
+    timer: Res<SpeedrunTimer>,
+    mut recorder: ResMut<GhostRecorder>,
+    query: Query<(&Transform, &Movement), With<PlayerMarker>>,
+) {
+    if !timer.running {
+        return;
+    }
+    for (transform, movement) in query.iter() {
+        recorder.frames.push(GhostFrame {
+            position: transform.translation.truncate(),
+            dashing: matches!(movement.dash.status, DashState::Dashing),
+        });
+    }
+}
+
+fn play_ghost_frame(
+    timer: Res<SpeedrunTimer>,
+    mut query: Query<(&mut Ghost, &mut Transform, &mut Sprite)>,
+) {
+    if !timer.running {
+        return;
+    }
+    for (mut ghost, mut transform, mut sprite) in query.iter_mut() {
+        let Some(frame) = ghost.frames.get(ghost.index).copied() else {
+            continue;
+        };
+        ghost.index += 1;
+        transform.translation.x = frame.position.x;
+        transform.translation.y = frame.position.y;
+        let alpha = if frame.dashing {
+            GHOST_ALPHA * 2.
+        } else {
+            GHOST_ALPHA
+        };
+        sprite.color = sprite.color.with_a(alpha);
+    }
+}
+
+fn save_best_ghost(recorder: Res<GhostRecorder>, level: Res<Level>) {
+    if recorder.frames.is_empty() {
+        return;
+    }
+    let filepath = ghost_file(&level.name);
+    let is_best = load_ghost(&filepath).is_none_or(|best| recorder.frames.len() < best.len());
+    if is_best {
+        save_ghost(&filepath, &recorder.frames);
+    }
+}
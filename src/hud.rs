@@ -0,0 +1,179 @@
+use bevy::prelude::*;
+use bevy::window::{PrimaryWindow, WindowResized};
+
+use crate::level::{Level, LevelStats};
+use crate::physics::Movement;
+use crate::player::PlayerMarker;
+use crate::state::{on_level_start, GameState, LevelEntity};
+use crate::util::*;
+
+pub struct HudPlugin;
+
+// the HUD is laid out for this window height and scaled from there
+const HUD_REFERENCE_HEIGHT: f32 = 720.;
+const HUD_FONT_SIZE: f32 = 20.;
+const DASH_PIP_SIZE: f32 = 12.;
+const DASH_PIP_FULL: Color = Color::rgb(0.4, 0.8, 1.);
+const DASH_PIP_EMPTY: Color = Color::rgba(1., 1., 1., 0.2);
+
+#[derive(Component)]
+pub struct HudLevelNameMarker;
+
+#[derive(Component)]
+pub struct HudTimerMarker;
+
+#[derive(Component)]
+pub struct HudDeathsMarker;
+
+#[derive(Component)]
+pub struct HudCollectiblesMarker;
+
+#[derive(Component)]
+pub struct DashPip(pub usize);
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        for schedule in on_level_start() {
+            app.add_systems(schedule, spawn_hud);
+        }
+        app.add_systems(Startup, scale_ui_to_window).add_systems(
+            Update,
+            (
+                scale_ui_to_window.run_if(on_event::<WindowResized>()),
+                (update_dash_pips, update_hud_text).run_if(in_state(GameState::Playing)),
+            ),
+        );
+    }
+}
+
+fn scale_ui_to_window(query: Query<&Window, With<PrimaryWindow>>, mut ui_scale: ResMut<UiScale>) {
+    if let Ok(window) = query.get_single() {
+        ui_scale.0 = window.height() / HUD_REFERENCE_HEIGHT;
+    }
+}
+
+fn hud_text(value: &str) -> TextBundle {
+    TextBundle::from_section(
+        value,
+        TextStyle {
+            font_size: HUD_FONT_SIZE,
+            ..default()
+        },
+    )
+}
+
+fn spawn_hud(mut commands: Commands, level: Res<Level>) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    position_type: PositionType::Absolute,
+                    justify_content: JustifyContent::SpaceBetween,
+                    padding: UiRect::all(Val::Px(8.)),
+                    ..default()
+                },
+                ..default()
+            },
+            LevelEntity,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        row_gap: Val::Px(4.),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|left| {
+                    left.spawn((hud_text(&level.name), HudLevelNameMarker));
+                    left.spawn((hud_text(""), HudTimerMarker));
+                    left.spawn(NodeBundle {
+                        style: Style {
+                            column_gap: Val::Px(4.),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|pips| {
+                        for i in 0..DEFAULT_DASH_COUNT {
+                            pips.spawn((
+                                NodeBundle {
+                                    style: Style {
+                                        width: Val::Px(DASH_PIP_SIZE),
+                                        height: Val::Px(DASH_PIP_SIZE),
+                                        ..default()
+                                    },
+                                    background_color: DASH_PIP_FULL.into(),
+                                    ..default()
+                                },
+                                DashPip(i),
+                            ));
+                        }
+                    });
+                });
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::End,
+                        row_gap: Val::Px(4.),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|right| {
+                    right.spawn((hud_text(""), HudCollectiblesMarker));
+                    right.spawn((hud_text(""), HudDeathsMarker));
+                });
+        });
+}
+
+fn update_dash_pips(
+    query: Query<&Movement, With<PlayerMarker>>,
+    mut query_pips: Query<(&DashPip, &mut BackgroundColor)>,
+) {
+    let Ok(movement) = query.get_single() else {
+        return;
+    };
+    for (pip, mut color) in query_pips.iter_mut() {
+        *color = if pip.0 < movement.dash.dash_count {
+            DASH_PIP_FULL.into()
+        } else {
+            DASH_PIP_EMPTY.into()
+        };
+    }
+}
+
+type HudTextQuery<'w, 's, T> = Query<'w, 's, &'static mut Text, With<T>>;
+
+fn update_hud_text(
+    stats: Res<LevelStats>,
+    mut queries: ParamSet<(
+        HudTextQuery<HudTimerMarker>,
+        HudTextQuery<HudDeathsMarker>,
+        HudTextQuery<HudCollectiblesMarker>,
+    )>,
+) {
+    for mut text in queries.p0().iter_mut() {
+        let minutes = (stats.elapsed / 60.) as u32;
+        text.sections[0].value = format!("{}:{:05.2}", minutes, stats.elapsed % 60.);
+    }
+    for mut text in queries.p1().iter_mut() {
+        text.sections[0].value = format!("deaths : {}", stats.deaths);
+    }
+    for mut text in queries.p2().iter_mut() {
+        text.sections[0].value = format!(
+            "collected : {} / {}",
+            stats.collected, stats.total_collectibles
+        );
+        if stats.total_strawberries > 0 {
+            text.sections[0].value += &format!(
+                "  strawberries : {} / {}",
+                stats.strawberries, stats.total_strawberries
+            );
+        }
+    }
+}
@@ -1,27 +1,99 @@
-use std::fs::File;
-use std::io::BufReader;
-use std::io::Read;
+use std::collections::HashMap;
+use std::fmt;
 
+use crate::entities::LdtkEntity;
 use crate::physics::*;
+use crate::state::*;
 use crate::util::*;
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext};
 use bevy::prelude::*;
-use serde_json::*;
+use bevy::transform::TransformSystem;
+use bevy::utils::BoxedFuture;
+use serde_json::Value;
 pub struct LevelPlugin;
 
-#[derive(Resource)]
+// keeps overlapping tiles of one layer in order without reaching the next layer
+const TILE_DEPTH_STEP: f32 = 0.0001;
+
+// depth between consecutive tiles of a layer, shrunk for big layers so all of
+// them fit in half a layer step
+fn tile_depth_step(tiles: usize) -> f32 {
+    TILE_DEPTH_STEP.min(TILE_LAYER_Z_STEP * 0.5 / tiles as f32)
+}
+
+// loaded through the asset server, then moved into a resource by the loading state
+#[derive(Resource, Asset, TypePath)]
 pub struct Level {
     pub world_pos: Vec3,
     // level_width: f32,
     // level_height: f32,
     // index: usize,
     pub block_size: Vec2,
+    pub layers: Vec<TileLayer>,
+    // visual tiles of every layer
     pub grid: Vec<Solid>,
+    pub colliders: Vec<Collider>,
     pub player_spawn_pos: Vec2,
+    pub music_file: Option<String>,
+    pub name: String,
+    pub entities: Vec<LdtkEntity>,
+}
+
+// one LDtk Tiles or AutoLayer layer, its tiles point back to it by index
+pub struct TileLayer {
+    pub identifier: String,
+    pub z: f32,
+    pub opacity: f32,
+    // size of one tile in the tileset, can differ from the level grid
+    pub tile_size: Vec2,
+    // how much of the camera movement the layer follows, 0 is fixed to the
+    // level and 1 is fixed to the screen
+    pub parallax: Vec2,
+}
+
+// where a level query first touched something solid
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LevelHit<'a> {
+    // contact point for a ray, center of the box at contact for a box cast
+    pub point: Vec2,
+    // surface normal, zero when the query started inside the collider
+    pub normal: Vec2,
+    pub distance: f32,
+    // grid cell that was hit, see Level::tile_at
+    pub tile: IVec2,
+    pub collider: &'a Collider,
+}
+
+// per attempt counters shown on the HUD, reset whenever a level starts
+#[derive(Resource, Default)]
+pub struct LevelStats {
+    pub elapsed: f32,
+    pub deaths: usize,
+    pub collected: usize,
+    pub total_collectibles: usize,
+    pub strawberries: usize,
+    pub total_strawberries: usize,
 }
 
 impl Plugin for LevelPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, startup_level_init);
+        app.init_asset::<Level>()
+            .init_resource::<LevelStats>()
+            .register_asset_loader(LevelLoader)
+            .add_systems(
+                Update,
+                update_level_timer.run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                PostUpdate,
+                scroll_parallax_layers
+                    .before(TransformSystem::TransformPropagate)
+                    .run_if(in_state(GameState::Playing)),
+            );
+        for schedule in on_level_start() {
+            app.add_systems(schedule, (spawn_level_tiles, reset_level_stats));
+        }
     }
 }
 
@@ -38,54 +110,316 @@ impl Level {
     //     }
     // }
 
-    pub fn new_from_json(filepath: String) -> Level {
-        let fs: File = File::open(filepath).unwrap();
-        let mut reader = BufReader::new(fs);
-        let mut buffer = String::new();
-        reader.read_to_string(&mut buffer).unwrap();
-        let value: Value = serde_json::from_str(buffer.as_str()).unwrap();
+    pub fn new_from_json(buffer: &str) -> Result<Level, LevelLoadError> {
+        let value: Value = serde_json::from_str(buffer)?;
 
-        let world_height = value["defaultLevelWidth"].as_f64().unwrap() as f32;
+        let world_height = as_f32(&value["defaultLevelWidth"], "defaultLevelWidth")?;
 
-        let spawn_pos_x = value["levels"][0]["layerInstances"][0]["entityInstances"][0]["px"][0]
-            .as_f64()
-            .unwrap() as f32;
-        let spawn_pos_y = value["levels"][0]["layerInstances"][0]["entityInstances"][0]["px"][1]
-            .as_f64()
-            .unwrap() as f32;
+        let entities = value["levels"][0]["layerInstances"][0]["entityInstances"]
+            .as_array()
+            .ok_or(LevelLoadError::MissingField("entityInstances"))?
+            .iter()
+            .map(|entity| LdtkEntity::from_json(entity, world_height, BLOCK_SIZE.x))
+            .collect::<Result<Vec<_>, _>>()?;
+        let spawn = entities
+            .iter()
+            .find(|entity| entity.identifier == "PlayerSpawnLocation")
+            .ok_or(LevelLoadError::MissingField("PlayerSpawnLocation"))?;
+        // the player is placed by the corner of the spawn entity
+        let player_spawn_pos = spawn.pos - spawn.size / 2.;
+
+        // optional "Music" string field on the LDtk level
+        let music_file = value["levels"][0]["fieldInstances"]
+            .as_array()
+            .and_then(|fields| {
+                fields
+                    .iter()
+                    .find(|field| field["__identifier"] == "Music")
+                    .and_then(|field| field["__value"].as_str())
+            })
+            .map(|file| file.to_string());
 
         let mut lvl = Level {
             world_pos: Vec3::new(0., 0., 0.),
             block_size: BLOCK_SIZE,
+            layers: Vec::new(),
             grid: Vec::new(),
-            player_spawn_pos: Vec2::new(spawn_pos_x, world_height - spawn_pos_y),
+            colliders: Vec::new(),
+            player_spawn_pos,
+            music_file,
+            name: value["levels"][0]["identifier"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            entities,
         };
-        let blocks = value["levels"][0]["layerInstances"][1]["gridTiles"]
+        // collision comes from the "Collision" IntGrid layer, levels without one
+        // collide with the tiles of their "Solids" layer
+        let collision = find_layer(&value, "Collision");
+
+        let layers = value["levels"][0]["layerInstances"]
             .as_array()
-            .unwrap();
-        for item in blocks {
-            let t_x: f32 = item["px"][0].as_f64().unwrap() as f32;
-            let t_y: f32 = item["px"][1].as_f64().unwrap() as f32;
-
-            // pos_x: t_x / BLOCK_SIZE.x,
-            //     pos_y: t_y / BLOCK_SIZE.y,
-            lvl.add_tile((
-                t_x / BLOCK_SIZE.x,
-                (world_height - t_y) / BLOCK_SIZE.y,
-                "tile_0069.png".to_string(),
-            ));
+            .ok_or(LevelLoadError::MissingField("layerInstances"))?;
+        // LDtk lists the top layer first, layers above the entities are drawn in
+        // front of the player
+        let entities_index = layers
+            .iter()
+            .position(|layer| layer["__type"] == "Entities")
+            .unwrap_or(0);
+        for (index, layer) in layers.iter().enumerate() {
+            // IntGrid layers only draw when they have auto rules and a tileset
+            let drawn = match layer["__type"].as_str() {
+                Some("Tiles" | "AutoLayer") => true,
+                Some("IntGrid") => !layer["__tilesetRelPath"].is_null(),
+                _ => false,
+            };
+            if !drawn {
+                continue;
+            }
+            let identifier = layer["__identifier"].as_str().unwrap_or_default();
+            let definition = layer_definition(&value, layer);
+            let parallax_factor = |name: &str| {
+                definition
+                    .and_then(|definition| definition[name].as_f64())
+                    .unwrap_or(0.) as f32
+            };
+            let texture_file = layer["__tilesetRelPath"]
+                .as_str()
+                .ok_or(LevelLoadError::MissingField("__tilesetRelPath"))?;
+            let layer_index = lvl.layers.len();
+            lvl.layers.push(TileLayer {
+                identifier: identifier.to_string(),
+                z: lvl.world_pos.z + (entities_index as f32 - index as f32) * TILE_LAYER_Z_STEP,
+                opacity: layer["__opacity"].as_f64().unwrap_or(1.) as f32,
+                tile_size: tileset_definition(&value, layer)
+                    .and_then(|tileset| tileset["tileGridSize"].as_f64())
+                    .map_or(lvl.block_size, |size| Vec2::splat(size as f32)),
+                parallax: Vec2::new(
+                    parallax_factor("parallaxFactorX"),
+                    parallax_factor("parallaxFactorY"),
+                ),
+            });
+            let collides = collision.is_none() && identifier == "Solids";
+
+            // hand placed and rule generated tiles share the same format
+            let tiles = layer["gridTiles"]
+                .as_array()
+                .into_iter()
+                .chain(layer["autoLayerTiles"].as_array())
+                .flatten();
+            for item in tiles {
+                let t_x = as_f32(&item["px"][0], "gridTiles.px")?;
+                let t_y = as_f32(&item["px"][1], "gridTiles.px")?;
+                let src = Vec2::new(
+                    as_f32(&item["src"][0], "gridTiles.src")?,
+                    as_f32(&item["src"][1], "gridTiles.src")?,
+                );
+                // bit 0 flips x, bit 1 flips y
+                let flip = item["f"].as_u64().unwrap_or(0);
+                let alpha = item["a"].as_f64().unwrap_or(1.) as f32;
+
+                let tile = (t_x / BLOCK_SIZE.x, (world_height - t_y) / BLOCK_SIZE.y);
+                lvl.add_tile(
+                    (tile.0, tile.1, texture_file.to_string()),
+                    layer_index,
+                    src,
+                    flip,
+                    alpha,
+                );
+                if collides {
+                    lvl.add_collider(tile, CollisionKind::Solid);
+                }
+            }
+        }
+
+        if let Some(layer) = collision {
+            let kinds = int_grid_kinds(&value, layer);
+            let columns = as_f32(&layer["__cWid"], "__cWid")? as usize;
+            let cells = layer["intGridCsv"]
+                .as_array()
+                .ok_or(LevelLoadError::MissingField("intGridCsv"))?;
+            for (i, cell) in cells.iter().enumerate() {
+                let Some(kind) = cell.as_i64().and_then(|cell| kinds.get(&cell)) else {
+                    continue;
+                };
+                let cx = (i % columns) as f32;
+                let cy = (i / columns) as f32;
+                lvl.add_collider(
+                    (cx, (world_height - cy * BLOCK_SIZE.y) / BLOCK_SIZE.y),
+                    *kind,
+                );
+            }
         }
-        lvl
+        // sprites stay per tile, collision uses the merged rectangles so the
+        // player does not snag on seams
+        lvl.colliders = merge_colliders(&lvl.colliders, lvl.world_pos.truncate(), lvl.block_size);
+        Ok(lvl)
     }
 
-    pub fn add_tile(&mut self, tile: (f32, f32, String)) -> &mut Level {
-        self.grid.push(Solid {
-            pos_x: (&self.world_pos.x + &self.block_size.x / 2.) + (&self.block_size.x * tile.0),
-            pos_y: (&self.world_pos.y + &self.block_size.y / 2.) + (&self.block_size.y * tile.1),
-            texture_file: tile.2,
+    // uses the same grid coordinates as add_tile
+    pub fn add_collider(&mut self, tile: (f32, f32), kind: CollisionKind) -> &mut Level {
+        self.colliders.push(Collider {
+            pos: Vec2::new(
+                (self.world_pos.x + self.block_size.x / 2.) + (self.block_size.x * tile.0),
+                (self.world_pos.y + self.block_size.y / 2.) + (self.block_size.y * tile.1),
+            ),
             bounds: HitBox {
-                half_size: BLOCK_SIZE / 2.,
+                half_size: self.block_size / 2.,
+            },
+            kind,
+        });
+        self
+    }
+
+    // colliders covering the entities with one of `identifiers`, each added once.
+    // Called through `bypass_change_detection` on level start so that the
+    // level does not count as changed
+    pub fn add_entity_colliders(&mut self, identifiers: &[&str], kind: CollisionKind) {
+        let missing: Vec<Collider> = self
+            .entities
+            .iter()
+            .filter(|item| identifiers.contains(&item.identifier.as_str()))
+            .map(|item| Collider {
+                pos: item.pos,
+                bounds: HitBox {
+                    half_size: item.size / 2.,
+                },
+                kind,
+            })
+            .filter(|item| !self.colliders.contains(item))
+            .collect();
+        self.colliders.extend(missing);
+    }
+
+    pub fn collider_at(&self, point: Vec2) -> Option<&Collider> {
+        self.colliders.iter().find(|item| item.contains(point))
+    }
+
+    // grid cell under a world point, the inverse of add_collider
+    pub fn tile_at(&self, point: Vec2) -> IVec2 {
+        ((point - self.world_pos.truncate()) / self.block_size)
+            .floor()
+            .as_ivec2()
+    }
+
+    // first solid collider along a ray, up to `max_distance`. Grazing an edge
+    // does not count as a hit
+    pub fn raycast(
+        &self,
+        origin: Vec2,
+        direction: Vec2,
+        max_distance: f32,
+    ) -> Option<LevelHit<'_>> {
+        self.sweep(origin, Vec2::ZERO, direction, max_distance).map(
+            |(distance, normal, collider)| {
+                let point = origin + direction.normalize() * distance;
+                LevelHit {
+                    point,
+                    normal,
+                    distance,
+                    tile: self.tile_at(point - normal * 0.5),
+                    collider,
+                }
+            },
+        )
+    }
+
+    // every collider of any kind overlapping a box, boxes that only touch an
+    // edge do not overlap
+    pub fn overlap_box(&self, center: Vec2, half_size: Vec2) -> Vec<&Collider> {
+        self.colliders
+            .iter()
+            .filter(|item| {
+                let gap = (item.pos - center).abs() - item.bounds.half_size - half_size;
+                gap.x < 0. && gap.y < 0.
+            })
+            .collect()
+    }
+
+    // moves a box along `motion` and stops it at the first solid collider,
+    // sliding along a surface it already touches is not a hit
+    pub fn box_cast(&self, center: Vec2, half_size: Vec2, motion: Vec2) -> Option<LevelHit<'_>> {
+        self.sweep(center, half_size, motion, motion.length()).map(
+            |(distance, normal, collider)| {
+                let point = center + motion.normalize() * distance;
+                let min = collider.pos - collider.bounds.half_size;
+                let max = collider.pos + collider.bounds.half_size;
+                // the part of the collider closest to the box
+                let contact = (point - normal * half_size).clamp(min, max);
+                LevelHit {
+                    point,
+                    normal,
+                    distance,
+                    tile: self.tile_at(contact - normal * 0.5),
+                    collider,
+                }
             },
+        )
+    }
+
+    // ray against every solid collider grown by `half_size`, returns the
+    // distance, normal and collider of the nearest hit
+    fn sweep(
+        &self,
+        origin: Vec2,
+        half_size: Vec2,
+        direction: Vec2,
+        max_distance: f32,
+    ) -> Option<(f32, Vec2, &Collider)> {
+        let direction = direction.try_normalize()?;
+        self.colliders
+            .iter()
+            .filter(|item| item.kind.is_solid())
+            .filter_map(|item| {
+                let min = item.pos - item.bounds.half_size - half_size;
+                let max = item.pos + item.bounds.half_size + half_size;
+                let (mut near, mut far) = (f32::NEG_INFINITY, max_distance);
+                let mut normal = Vec2::ZERO;
+                for axis in 0..2 {
+                    if direction[axis] == 0. {
+                        // parallel to this pair of sides, only hits when between them
+                        if origin[axis] <= min[axis] || origin[axis] >= max[axis] {
+                            return None;
+                        }
+                        continue;
+                    }
+                    let t1 = (min[axis] - origin[axis]) / direction[axis];
+                    let t2 = (max[axis] - origin[axis]) / direction[axis];
+                    // the side entered last is the one that was hit
+                    if t1.min(t2) > near {
+                        near = t1.min(t2);
+                        normal = Vec2::ZERO;
+                        normal[axis] = -direction[axis].signum();
+                    }
+                    far = t1.max(t2).min(far);
+                }
+                if near < 0. {
+                    // already inside
+                    near = 0.;
+                    normal = Vec2::ZERO;
+                }
+                (near < far).then_some((near, normal, item))
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+    }
+
+    pub fn add_tile(
+        &mut self,
+        tile: (f32, f32, String),
+        layer: usize,
+        src: Vec2,
+        flip: u64,
+        alpha: f32,
+    ) -> &mut Level {
+        self.grid.push(Solid {
+            pos_x: (self.world_pos.x + self.block_size.x / 2.) + (self.block_size.x * tile.0),
+            pos_y: (self.world_pos.y + self.block_size.y / 2.) + (self.block_size.y * tile.1),
+            texture_file: tile.2,
+            src,
+            layer,
+            flip_x: flip & 1 != 0,
+            flip_y: flip & 2 != 0,
+            alpha,
         });
         self
     }
@@ -106,16 +440,371 @@ impl Level {
     // }
 }
 
-fn startup_level_init(mut commands: Commands, level: Res<Level>, asset_server: Res<AssetServer>) {
+fn as_f32(value: &Value, name: &'static str) -> Result<f32, LevelLoadError> {
+    value
+        .as_f64()
+        .map(|v| v as f32)
+        .ok_or(LevelLoadError::MissingField(name))
+}
+
+fn find_layer<'a>(value: &'a Value, identifier: &str) -> Option<&'a Value> {
+    value["levels"][0]["layerInstances"]
+        .as_array()?
+        .iter()
+        .find(|layer| layer["__identifier"] == identifier)
+}
+
+fn layer_definition<'a>(value: &'a Value, layer: &Value) -> Option<&'a Value> {
+    value["defs"]["layers"]
+        .as_array()?
+        .iter()
+        .find(|item| item["uid"] == layer["layerDefUid"])
+}
+
+fn tileset_definition<'a>(value: &'a Value, layer: &Value) -> Option<&'a Value> {
+    value["defs"]["tilesets"]
+        .as_array()?
+        .iter()
+        .find(|item| item["uid"] == layer["__tilesetDefUid"])
+}
+
+// IntGrid values mapped to collision kinds through their identifiers in the
+// layer definition
+fn int_grid_kinds(value: &Value, layer: &Value) -> HashMap<i64, CollisionKind> {
+    let definition = layer_definition(value, layer);
+    let mut kinds = HashMap::new();
+    for item in definition
+        .and_then(|item| item["intGridValues"].as_array())
+        .into_iter()
+        .flatten()
+    {
+        let identifier = item["identifier"].as_str().unwrap_or_default();
+        match (
+            item["value"].as_i64(),
+            CollisionKind::from_identifier(identifier),
+        ) {
+            (Some(value), Some(kind)) => {
+                kinds.insert(value, kind);
+            }
+            _ => warn!("unknown IntGrid collision value {}", item),
+        }
+    }
+    kinds
+}
+
+#[derive(Debug)]
+pub enum LevelLoadError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    MissingField(&'static str),
+}
+
+impl fmt::Display for LevelLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelLoadError::Io(err) => write!(f, "could not read level : {}", err),
+            LevelLoadError::Json(err) => write!(f, "invalid level json : {}", err),
+            LevelLoadError::MissingField(name) => write!(f, "level is missing field {}", name),
+        }
+    }
+}
+
+impl std::error::Error for LevelLoadError {}
+
+impl From<std::io::Error> for LevelLoadError {
+    fn from(err: std::io::Error) -> Self {
+        LevelLoadError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for LevelLoadError {
+    fn from(err: serde_json::Error) -> Self {
+        LevelLoadError::Json(err)
+    }
+}
+
+#[derive(Default)]
+pub struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    type Asset = Level;
+    type Settings = ();
+    type Error = LevelLoadError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Level, LevelLoadError>> {
+        Box::pin(async move {
+            let mut buffer = String::new();
+            reader.read_to_string(&mut buffer).await?;
+            Level::new_from_json(&buffer)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ldtk", "json"]
+    }
+}
+
+pub fn reset_level_stats(mut stats: ResMut<LevelStats>) {
+    *stats = LevelStats::default();
+}
+
+fn update_level_timer(mut stats: ResMut<LevelStats>, time: Res<Time>) {
+    stats.elapsed += time.delta_seconds();
+}
+
+// moves a layer against the camera, relative to where the camera looks at the
+// spawn point
+#[derive(Component)]
+pub struct ParallaxLayer(pub Vec2);
+
+fn spawn_level_tiles(mut commands: Commands, level: Res<Level>, asset_server: Res<AssetServer>) {
+    let parents: Vec<Entity> = level
+        .layers
+        .iter()
+        .map(|layer| {
+            let mut parent = commands.spawn((
+                SpatialBundle::from_transform(Transform::from_xyz(0., 0., layer.z)),
+                Name::new(layer.identifier.clone()),
+                LevelEntity,
+            ));
+            if layer.parallax != Vec2::ZERO {
+                parent.insert(ParallaxLayer(layer.parallax));
+            }
+            parent.id()
+        })
+        .collect();
+    // tiles of a layer can overlap, later ones are drawn on top
+    let mut counts = vec![0; level.layers.len()];
     for item in &level.grid {
-        commands.spawn((SpriteBundle {
-            texture: asset_server.load(&item.texture_file),
-            transform: Transform::from_translation(Vec3::new(
-                item.pos_x,
-                item.pos_y,
-                level.world_pos.z,
-            )),
-            ..default()
-        },));
+        counts[item.layer] += 1;
+    }
+    let mut depths = vec![0.; level.layers.len()];
+    for item in &level.grid {
+        let layer = &level.layers[item.layer];
+        let depth = depths[item.layer];
+        depths[item.layer] += tile_depth_step(counts[item.layer]);
+        let tile = commands
+            .spawn(SpriteBundle {
+                texture: asset_server.load(&item.texture_file),
+                sprite: Sprite {
+                    color: Color::WHITE.with_a(layer.opacity * item.alpha),
+                    rect: Some(Rect::from_corners(item.src, item.src + layer.tile_size)),
+                    // scaled to the level grid when the tileset uses another size
+                    custom_size: Some(level.block_size),
+                    flip_x: item.flip_x,
+                    flip_y: item.flip_y,
+                    ..default()
+                },
+                transform: Transform::from_xyz(item.pos_x, item.pos_y, depth),
+                ..default()
+            })
+            .id();
+        commands.entity(parents[item.layer]).add_child(tile);
+    }
+}
+
+fn scroll_parallax_layers(
+    level: Res<Level>,
+    query_cam: Query<&Transform, With<CameraMarker>>,
+    mut query: Query<(&ParallaxLayer, &mut Transform), Without<CameraMarker>>,
+) {
+    let Ok(cam) = query_cam.get_single() else {
+        return;
+    };
+    let offset = cam.translation.truncate() - level.player_spawn_pos;
+    for (parallax, mut transform) in query.iter_mut() {
+        transform.translation.x = offset.x * parallax.0.x;
+        transform.translation.y = offset.y * parallax.0.y;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::tests::test_level;
+
+    #[test]
+    fn tile_depths_stay_inside_their_layer() {
+        assert_eq!(tile_depth_step(100), TILE_DEPTH_STEP);
+        for tiles in [1, 20_000, 20_001, 1_000_000] {
+            let last = tile_depth_step(tiles) * (tiles - 1) as f32;
+            assert!(
+                last < TILE_LAYER_Z_STEP * 0.5,
+                "{} tiles reach {}",
+                tiles,
+                last
+            );
+        }
+    }
+
+    #[test]
+    fn entity_colliders_are_added_once() {
+        let mut level = test_level(&["....", "####"]);
+        let floor = level.colliders.len();
+        for (identifier, x) in [
+            ("BreakableBlock", 8.),
+            ("BreakableBlock", 24.),
+            ("Coin", 40.),
+        ] {
+            level.entities.push(LdtkEntity {
+                identifier: identifier.to_string(),
+                iid: format!("{}", x),
+                pos: Vec2::new(x, 24.),
+                size: Vec2::splat(16.),
+                fields: default(),
+            });
+        }
+        level.add_entity_colliders(&["BreakableBlock"], CollisionKind::Breakable);
+        level.add_entity_colliders(&["BreakableBlock"], CollisionKind::Breakable);
+        assert_eq!(level.colliders.len(), floor + 2);
+        assert!(level.collider_at(Vec2::new(40., 24.)).is_none());
+        // a broken block comes back on the next run
+        let broken = level
+            .colliders
+            .iter()
+            .position(|item| item.contains(Vec2::new(24., 24.)))
+            .unwrap();
+        level.colliders.remove(broken);
+        level.add_entity_colliders(&["BreakableBlock"], CollisionKind::Breakable);
+        assert_eq!(
+            level.collider_at(Vec2::new(24., 24.)).map(|item| item.kind),
+            Some(CollisionKind::Breakable)
+        );
+    }
+
+    // x 0..16 is a wall, the floor top is at y 16 and a one-way ledge sits at
+    // y 48..64 over x 64..96
+    const ROOM: [&str; 5] = ["#.......", "#...--..", "#.......", "#.......", "########"];
+
+    #[test]
+    fn raycast_reports_point_normal_and_tile() {
+        let level = test_level(&ROOM);
+        let hit = level
+            .raycast(Vec2::new(72., 40.), Vec2::NEG_X, 100.)
+            .unwrap();
+        assert_eq!(hit.point, Vec2::new(16., 40.));
+        assert_eq!(hit.normal, Vec2::X);
+        assert_eq!(hit.distance, 56.);
+        assert_eq!(hit.tile, IVec2::new(0, 2));
+        assert_eq!(hit.collider.kind, CollisionKind::Solid);
+
+        let hit = level
+            .raycast(Vec2::new(72., 40.), Vec2::NEG_Y, 100.)
+            .unwrap();
+        assert_eq!(hit.point, Vec2::new(72., 16.));
+        assert_eq!(hit.normal, Vec2::Y);
+        assert_eq!(hit.tile, IVec2::new(4, 0));
+    }
+
+    #[test]
+    fn raycast_ignores_non_solid_and_respects_range() {
+        let level = test_level(&ROOM);
+        // straight up through the one-way ledge and out of the level
+        assert!(level.raycast(Vec2::new(72., 24.), Vec2::Y, 200.).is_none());
+        assert!(level
+            .raycast(Vec2::new(72., 40.), Vec2::NEG_X, 50.)
+            .is_none());
+        assert!(level
+            .raycast(Vec2::new(72., 40.), Vec2::ZERO, 50.)
+            .is_none());
+    }
+
+    #[test]
+    fn raycast_diagonal_hits_the_side_entered_last() {
+        let level = test_level(&ROOM);
+        // reaches the wall at y 24, above the floor
+        let hit = level
+            .raycast(Vec2::new(40., 48.), Vec2::new(-1., -1.), 100.)
+            .unwrap();
+        assert!(hit.point.abs_diff_eq(Vec2::new(16., 24.), 1e-4));
+        assert_eq!(hit.normal, Vec2::X);
+        // reaches the floor at x 24, right of the wall
+        let hit = level
+            .raycast(Vec2::new(40., 32.), Vec2::new(-1., -1.), 100.)
+            .unwrap();
+        assert!(hit.point.abs_diff_eq(Vec2::new(24., 16.), 1e-4));
+        assert_eq!(hit.normal, Vec2::Y);
+        assert_eq!(hit.tile, IVec2::new(1, 0));
+    }
+
+    #[test]
+    fn raycast_from_inside_hits_at_the_start() {
+        let level = test_level(&ROOM);
+        let hit = level.raycast(Vec2::new(8., 40.), Vec2::X, 100.).unwrap();
+        assert_eq!(hit.distance, 0.);
+        assert_eq!(hit.normal, Vec2::ZERO);
+    }
+
+    #[test]
+    fn overlap_box_finds_every_kind_but_not_touching_ones() {
+        let level = test_level(&ROOM);
+        let kinds = |center: Vec2, half_size: Vec2| {
+            let mut kinds: Vec<CollisionKind> = level
+                .overlap_box(center, half_size)
+                .iter()
+                .map(|item| item.kind)
+                .collect();
+            kinds.sort_by_key(|kind| *kind as u8);
+            kinds
+        };
+        // resting in the corner, then pushed a pixel into it
+        assert!(kinds(Vec2::new(24., 24.), Vec2::splat(8.)).is_empty());
+        assert_eq!(
+            kinds(Vec2::new(23., 23.), Vec2::splat(8.)),
+            vec![CollisionKind::Solid; 3]
+        );
+        assert_eq!(
+            kinds(Vec2::new(72., 50.), Vec2::splat(4.)),
+            vec![CollisionKind::OneWay]
+        );
+    }
+
+    #[test]
+    fn box_cast_stops_the_box_against_a_wall() {
+        let level = test_level(&ROOM);
+        let half = Vec2::splat(8.);
+        let hit = level
+            .box_cast(Vec2::new(72., 40.), half, Vec2::new(-100., 0.))
+            .unwrap();
+        assert_eq!(hit.point, Vec2::new(24., 40.));
+        assert_eq!(hit.normal, Vec2::X);
+        assert_eq!(hit.distance, 48.);
+        assert_eq!(hit.tile, IVec2::new(0, 2));
+        // too short to reach
+        assert!(level
+            .box_cast(Vec2::new(72., 40.), half, Vec2::new(-40., 0.))
+            .is_none());
+    }
+
+    #[test]
+    fn box_cast_slides_along_a_touching_floor() {
+        let level = test_level(&ROOM);
+        let half = Vec2::splat(8.);
+        // resting on the floor, moving right touches nothing
+        assert!(level
+            .box_cast(Vec2::new(40., 24.), half, Vec2::new(60., 0.))
+            .is_none());
+        // moving away from the wall it touches
+        assert!(level
+            .box_cast(Vec2::new(24., 40.), half, Vec2::new(30., 0.))
+            .is_none());
+        // pushing into the wall it touches stops at once
+        let hit = level
+            .box_cast(Vec2::new(24., 40.), half, Vec2::new(-30., 0.))
+            .unwrap();
+        assert_eq!(hit.distance, 0.);
+        assert_eq!(hit.normal, Vec2::X);
+        // dropping onto the floor
+        let hit = level
+            .box_cast(Vec2::new(40., 60.), half, Vec2::new(0., -100.))
+            .unwrap();
+        assert_eq!(hit.point, Vec2::new(40., 24.));
+        assert_eq!(hit.normal, Vec2::Y);
+        assert_eq!(hit.tile, IVec2::new(2, 0));
     }
 }
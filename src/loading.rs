@@ -0,0 +1,182 @@
+use bevy::asset::{LoadState, UntypedHandle};
+use bevy::prelude::*;
+
+use crate::audio::SoundEvent;
+use crate::level::Level;
+use crate::state::{despawn_all, GameState};
+use crate::util::*;
+
+pub struct LoadingPlugin;
+
+impl Plugin for LoadingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnEnter(GameState::Loading),
+            (start_loading, spawn_loading_screen),
+        )
+        .add_systems(
+            Update,
+            (
+                check_level_loaded,
+                check_assets_loaded.after(check_level_loaded),
+            )
+                .run_if(in_state(GameState::Loading)),
+        )
+        .add_systems(
+            OnExit(GameState::Loading),
+            despawn_all::<LoadingScreenMarker>,
+        );
+    }
+}
+
+pub struct LoadingEntry {
+    pub path: String,
+    pub handle: UntypedHandle,
+    // optional assets (audio) only log a warning when they fail
+    pub required: bool,
+}
+
+// keeps every handle alive for the rest of the session so nothing gets unloaded
+#[derive(Resource, Default)]
+pub struct LoadingAssets {
+    pub level: Handle<Level>,
+    pub level_ready: bool,
+    pub entries: Vec<LoadingEntry>,
+    pub failed: Option<String>,
+}
+
+impl LoadingAssets {
+    fn add<A: Asset>(&mut self, asset_server: &AssetServer, path: &str, required: bool) {
+        if self.entries.iter().any(|entry| entry.path == path) {
+            return;
+        }
+        let handle: Handle<A> = asset_server.load(path.to_string());
+        self.entries.push(LoadingEntry {
+            path: path.to_string(),
+            handle: handle.untyped(),
+            required,
+        });
+    }
+}
+
+#[derive(Component)]
+pub struct LoadingScreenMarker;
+
+#[derive(Component)]
+pub struct LoadingTextMarker;
+
+fn start_loading(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let mut loading = LoadingAssets {
+        level: asset_server.load(LEVEL_FILE),
+        ..default()
+    };
+    loading.add::<Image>(&asset_server, PLAYER_TEXTURE, true);
+    for event in SoundEvent::ALL {
+        loading.add::<AudioSource>(&asset_server, event.file(), false);
+    }
+    commands.insert_resource(loading);
+}
+
+fn spawn_loading_screen(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: Color::BLACK.into(),
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            LoadingScreenMarker,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "loading",
+                    TextStyle {
+                        font_size: 20.,
+                        ..default()
+                    },
+                ),
+                LoadingTextMarker,
+            ));
+        });
+}
+
+// the level has to be parsed before we know which textures and music it needs
+fn check_level_loaded(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut loading: ResMut<LoadingAssets>,
+    mut levels: ResMut<Assets<Level>>,
+) {
+    if loading.level_ready || loading.failed.is_some() {
+        return;
+    }
+    match asset_server.load_state(&loading.level) {
+        LoadState::Loaded => {
+            let Some(level) = levels.remove(&loading.level) else {
+                return;
+            };
+            for item in &level.grid {
+                loading.add::<Image>(&asset_server, &item.texture_file, true);
+            }
+            if let Some(music_file) = &level.music_file {
+                loading.add::<AudioSource>(&asset_server, music_file, false);
+            }
+            loading.level_ready = true;
+            commands.insert_resource(level);
+        }
+        LoadState::Failed => {
+            loading.failed = Some(LEVEL_FILE.to_string());
+        }
+        _ => {}
+    }
+}
+
+fn check_assets_loaded(
+    asset_server: Res<AssetServer>,
+    mut loading: ResMut<LoadingAssets>,
+    mut query_text: Query<&mut Text, With<LoadingTextMarker>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let mut done = 0;
+    let mut failed = None;
+    for entry in &loading.entries {
+        match asset_server.load_state(&entry.handle) {
+            LoadState::Loaded => done += 1,
+            LoadState::Failed if entry.required => failed = Some(entry.path.clone()),
+            LoadState::Failed => done += 1,
+            _ => {}
+        }
+    }
+    if loading.failed.is_none() {
+        loading.failed = failed;
+    }
+    // the level itself counts as one entry
+    let total = loading.entries.len() + 1;
+    if loading.level_ready {
+        done += 1;
+    }
+
+    if let Ok(mut text) = query_text.get_single_mut() {
+        text.sections[0].value = match &loading.failed {
+            Some(path) => format!("failed to load : {}", path),
+            None => format!("loading : {} / {}", done, total),
+        };
+    }
+
+    if loading.failed.is_none() && loading.level_ready && done == total {
+        for entry in &loading.entries {
+            if !entry.required && asset_server.load_state(&entry.handle) == LoadState::Failed {
+                warn!("optional asset {} failed to load", entry.path);
+            }
+        }
+        next_state.set(GameState::MainMenu);
+    }
+}
@@ -1,12 +1,40 @@
+use abilities::AbilitiesPlugin;
+use audio::SoundPlugin;
 use bevy::{prelude::*, render::camera::ScalingMode};
-use level::{Level, LevelPlugin};
-use physics::{Movement, PhysicsPlugin};
+use climbing::ClimbingPlugin;
+use collectibles::CollectiblesPlugin;
+use debug::DebugPlugin;
+use entities::EntitiesPlugin;
+use ghost::GhostPlugin;
+use hud::HudPlugin;
+use level::LevelPlugin;
+use loading::LoadingPlugin;
+use physics::PhysicsPlugin;
 use player::{PlayerMarker, PlayerPlugin};
+use save::SavePlugin;
+use speedrun::SpeedrunPlugin;
+use state::{GameState, StatePlugin};
+use telemetry::TelemetryPlugin;
 use util::CameraMarker;
+use water::WaterPlugin;
+mod abilities;
+mod audio;
+mod climbing;
+mod collectibles;
+mod debug;
+mod entities;
+mod ghost;
+mod hud;
 mod level;
+mod loading;
 mod physics;
 mod player;
+mod save;
+mod speedrun;
+mod state;
+mod telemetry;
 mod util;
+mod water;
 
 fn main() {
     App::new()
@@ -20,14 +48,25 @@ fn main() {
         //         .repeat((79., 1., "tile_0069.png".to_string()), 0., 42.)
         //         .repeat((0., 44., "tile_0069.png".to_string()), 79., 0.),
         // )
-        .insert_resource(Level::new_from_json(
-            "W:\\Rust Projects\\basic_platformer\\assets\\level_1.json".to_string(),
-        ))
         .add_plugins(LevelPlugin)
+        .add_plugins(EntitiesPlugin)
         .add_systems(Startup, setup_world)
-        .add_systems(Update, (update_camera, draw_dash_distance))
+        .add_plugins(StatePlugin)
+        .add_plugins(LoadingPlugin)
+        .add_systems(Update, update_camera.run_if(in_state(GameState::Playing)))
         .add_plugins(PhysicsPlugin)
         .add_plugins(PlayerPlugin)
+        .add_plugins(SoundPlugin)
+        .add_plugins(DebugPlugin)
+        .add_plugins(TelemetryPlugin)
+        .add_plugins(HudPlugin)
+        .add_plugins(SpeedrunPlugin)
+        .add_plugins(GhostPlugin)
+        .add_plugins(SavePlugin)
+        .add_plugins(CollectiblesPlugin)
+        .add_plugins(AbilitiesPlugin)
+        .add_plugins(ClimbingPlugin)
+        .add_plugins(WaterPlugin)
         .run();
 }
 
@@ -36,29 +75,15 @@ fn setup_world(mut commands: Commands) {
         transform: Transform::from_translation(Vec3::new(640., 360., 0.)),
         ..default()
     };
+    // the camera sits at z 0, look both ways so background layers (negative z)
+    // and foreground layers (positive z) are drawn
+    cam.projection.near = -1000.;
     cam.projection.scaling_mode = ScalingMode::AutoMax {
         max_width: 640.,
         max_height: 360.,
     };
 
-    let text_style = TextStyle {
-        font_size: 20.,
-        ..default()
-    };
-
     commands.spawn((cam, CameraMarker));
-    commands.spawn(
-        TextBundle::from_sections([
-            TextSection::new("dash_distance : ", text_style.clone()),
-            TextSection::new("", text_style.clone()),
-        ])
-        .with_style(Style {
-            position_type: PositionType::Absolute,
-            top: Val::Px(5.),
-            left: Val::Px(5.),
-            ..default()
-        }),
-    );
 }
 fn update_camera(
     mut query_cam: Query<&mut Transform, With<CameraMarker>>,
@@ -68,12 +93,3 @@ fn update_camera(
     let player = query_player.single();
     cam.translation = player.translation;
 }
-
-fn draw_dash_distance(
-    query: Query<&Movement, With<PlayerMarker>>,
-    mut query_text: Query<&mut Text>,
-) {
-    let movement = query.single();
-    let mut text = query_text.single_mut();
-    text.sections[1].value = movement.dash.distance.to_string();
-}
@@ -1,7 +1,10 @@
+use std::collections::HashMap;
 use std::time::Duration;
 
+use crate::audio::SoundEvent;
 use crate::level::*;
-use crate::player::PlayerMarker;
+use crate::player::{PlayerDeath, PlayerMarker};
+use crate::state::GameState;
 use crate::util::*;
 use bevy::prelude::*;
 
@@ -9,7 +12,77 @@ pub struct PhysicsPlugin;
 
 impl Plugin for PhysicsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, (update_physics, dash_timer));
+        app.init_resource::<PhysicsConfig>()
+            .init_resource::<DashFreeze>()
+            .configure_sets(
+                Update,
+                (PhysicsSet::PreStep, PhysicsSet::Step, PhysicsSet::PostStep)
+                    .chain()
+                    .after(tick_dash_freeze)
+                    .run_if(in_state(GameState::Playing))
+                    .run_if(not_frozen),
+            )
+            .add_systems(
+                Update,
+                (
+                    tick_dash_freeze.run_if(in_state(GameState::Playing)),
+                    crouch.in_set(PhysicsSet::PreStep),
+                    stand_up_on_death
+                        .run_if(in_state(GameState::Playing))
+                        .run_if(on_event::<PlayerDeath>())
+                        .before(crouch),
+                    (update_physics, dash_timer).in_set(PhysicsSet::Step),
+                ),
+            );
+    }
+}
+
+// abilities and other movement modifiers hook in around the physics step
+// instead of patching update_physics
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PhysicsSet {
+    // input has been read, velocity can still be changed before moving
+    PreStep,
+    Step,
+    // the player has moved and collided this frame
+    PostStep,
+}
+
+// movement tuning that can be changed at runtime
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct PhysicsConfig {
+    // how far the player is pushed sideways around a ceiling corner, 0 disables it
+    pub corner_correction: f32,
+    // how far the player is lifted onto a ledge it barely missed, 0 disables it
+    pub ledge_assist: f32,
+    // jumping out of a dash on the ground keeps the dash speed, at least this
+    // multiple of the run cap. Heights are multiples of a normal jump
+    pub super_jump_speed: f32,
+    pub super_jump_height: f32,
+    // landing from a diagonal down dash
+    pub wavedash_speed: f32,
+    // jumping off a wall in the middle of a dash
+    pub wall_jump_speed: f32,
+    pub wall_jump_height: f32,
+    // seconds after a dash ends that a jump still counts as a dash jump
+    pub dash_jump_grace: f32,
+    // how fast horizontal speed above the run cap bleeds off, per second
+    pub momentum_decay: f32,
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        PhysicsConfig {
+            corner_correction: CORNER_CORRECTION,
+            ledge_assist: LEDGE_ASSIST,
+            super_jump_speed: SUPER_JUMP_SPEED,
+            super_jump_height: SUPER_JUMP_HEIGHT,
+            wavedash_speed: WAVEDASH_SPEED,
+            wall_jump_speed: WALL_JUMP_SPEED,
+            wall_jump_height: WALL_JUMP_HEIGHT,
+            dash_jump_grace: DASH_JUMP_GRACE,
+            momentum_decay: MOMENTUM_DECAY,
+        }
     }
 }
 
@@ -46,6 +119,28 @@ pub struct Movement {
     pub directional: Vec2,
     pub dash: Dash,
     pub jump: bool,
+    // jump is held down, `jump` is cleared once it is used
+    pub jump_held: bool,
+    // seconds left for the last jump press to count as fresh, a dash jump
+    // needs one so a jump held from before the dash does not fire it
+    pub jump_pressed: f32,
+    // hitbox shrunk to `CROUCH_HALF_HEIGHT`, sliding while still fast
+    pub crouching: bool,
+    pub sliding: bool,
+    // attached to a ladder or vine
+    pub climbing: bool,
+    // hanging from a grapple rope
+    pub swinging: bool,
+    // center of the player is in water
+    pub swimming: bool,
+    // grapple was pressed this frame
+    pub grapple: bool,
+    pub grapple_held: bool,
+    pub grab_held: bool,
+    // holding onto a wall, costs `Stamina`
+    pub grabbing: bool,
+    // last horizontal input direction, used to dash without a direction held
+    pub facing: f32,
 }
 
 impl Default for Movement {
@@ -54,42 +149,140 @@ impl Default for Movement {
             directional: Vec2::ZERO,
             dash: Dash::default(),
             jump: false,
+            jump_held: false,
+            jump_pressed: 0.,
+            crouching: false,
+            sliding: false,
+            climbing: false,
+            swinging: false,
+            swimming: false,
+            grapple: false,
+            grapple_held: false,
+            grab_held: false,
+            grabbing: false,
+            facing: 1.,
         }
     }
 }
 
+// seconds of wall holding left, refilled together with the dash count
+#[derive(Component)]
+pub struct Stamina(pub f32);
+
+impl Default for Stamina {
+    fn default() -> Self {
+        Stamina(STAMINA_MAX)
+    }
+}
+
 #[derive(Component)]
 pub struct Grounded(pub bool);
 
+// visual tile, collision is described separately by `Collider`
 #[derive(Component)]
 pub struct Solid {
     pub pos_x: f32,
     pub pos_y: f32,
     pub texture_file: String,
+    // top left corner of the tile in its tileset
+    pub src: Vec2,
+    // index into Level::layers
+    pub layer: usize,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    pub alpha: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CollisionKind {
+    Solid,
+    // only stops the player when falling onto it from above
+    OneWay,
+    Hazard,
+    Water,
+    // solid with almost no friction
+    Ice,
+    // solid until a ground pound lands on it
+    Breakable,
+    // ladders and vines, never blocks
+    Climbable,
+}
+
+impl CollisionKind {
+    // identifiers of the IntGrid values in the LDtk layer definition
+    pub fn from_identifier(identifier: &str) -> Option<CollisionKind> {
+        match identifier.to_lowercase().as_str() {
+            "solid" => Some(CollisionKind::Solid),
+            "one_way" | "oneway" => Some(CollisionKind::OneWay),
+            "hazard" => Some(CollisionKind::Hazard),
+            "water" => Some(CollisionKind::Water),
+            "ice" => Some(CollisionKind::Ice),
+            "ladder" | "vine" | "climbable" => Some(CollisionKind::Climbable),
+            _ => None,
+        }
+    }
+
+    // always in the way, from every side
+    pub fn is_solid(self) -> bool {
+        matches!(
+            self,
+            CollisionKind::Solid | CollisionKind::Ice | CollisionKind::Breakable
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Collider {
+    pub pos: Vec2,
     pub bounds: HitBox,
+    pub kind: CollisionKind,
+}
+
+impl Collider {
+    pub fn contains(&self, point: Vec2) -> bool {
+        let delta = (point - self.pos).abs();
+        delta.x <= self.bounds.half_size.x && delta.y <= self.bounds.half_size.y
+    }
+
+    pub fn top(&self) -> f32 {
+        self.pos.y + self.bounds.half_size.y
+    }
+
+    // whether a probe at `point` stops the move, `feet_y` is the bottom of the
+    // player before moving `move_y`
+    pub fn blocks(&self, point: Vec2, feet_y: f32, move_y: f32) -> bool {
+        if !self.contains(point) {
+            return false;
+        }
+        match self.kind {
+            CollisionKind::OneWay => move_y < 0. && feet_y >= self.top(),
+            kind => kind.is_solid(),
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default, Clone, Copy)]
 pub enum DashState {
     Started,
     Dashing,
     Finished,
+    #[default]
     Ready,
     Cancelled,
 }
 
-impl Default for DashState {
-    fn default() -> Self {
-        Self::Ready
-    }
-}
-
 pub struct Dash {
     pub dash_count: usize,
     pub dash_timer: Timer,
     pub status: DashState,
     pub distance: f32,
     pub start_point: Vec3,
+    // seconds left before a dash press that could not start yet is dropped
+    pub buffered: f32,
+    // snapped direction of the current or last dash
+    pub direction: Vec2,
+    // seconds left in which a jump still turns into a dash technique
+    pub grace: f32,
 }
 impl Default for Dash {
     fn default() -> Self {
@@ -102,10 +295,89 @@ impl Default for Dash {
             status: DashState::default(),
             distance: 0.,
             start_point: Vec3::ZERO,
+            buffered: 0.,
+            direction: Vec2::ZERO,
+            grace: 0.,
+        }
+    }
+}
+
+// seconds the player stays frozen after a dash starts, so the dash reads clearly
+#[derive(Resource, Default)]
+pub struct DashFreeze(pub f32);
+
+fn tick_dash_freeze(mut freeze: ResMut<DashFreeze>, time: Res<Time>) {
+    freeze.0 = (freeze.0 - time.delta_seconds()).max(0.);
+}
+
+fn not_frozen(freeze: Res<DashFreeze>) -> bool {
+    freeze.0 <= 0.
+}
+
+// nearest of the eight directions, None when there is no direction at all
+pub fn snap_direction(direction: Vec2) -> Option<Vec2> {
+    if direction.length_squared() < f32::EPSILON {
+        return None;
+    }
+    let step = std::f32::consts::FRAC_PI_4;
+    let angle = (direction.y.atan2(direction.x) / step).round() * step;
+    Some(Vec2::from_angle(angle))
+}
+
+// down on the ground crouches, down at speed slides. Letting go only stands
+// back up once there is room above
+pub fn crouch(
+    level: Res<Level>,
+    mut query: Query<(
+        &mut Transform,
+        &mut HitBox,
+        &mut Movement,
+        &Velocity,
+        &Grounded,
+    )>,
+) {
+    let full = HitBox {
+        half_size: PLAYER_HALF_SIZE,
+    };
+    for (mut transform, mut hitbox, mut movement, vel, grounded) in query.iter_mut() {
+        let on_ground = grounded.0 || standing(&level.colliders, transform.translation, *hitbox);
+        let dashing = matches!(movement.dash.status, DashState::Dashing);
+        let down = movement.directional.y < -0.5;
+        if !movement.crouching
+            && down
+            && on_ground
+            && !dashing
+            && !movement.climbing
+            && !movement.swimming
+        {
+            movement.crouching = true;
+            movement.sliding = vel.0.x.abs() >= SLIDE_MIN_SPEED;
+            // keep the feet where they are
+            transform.translation.y -= hitbox.half_size.y - CROUCH_HALF_HEIGHT;
+            hitbox.half_size.y = CROUCH_HALF_HEIGHT;
+        } else if movement.crouching
+            && !down
+            && can_stand(&level.colliders, transform.translation, *hitbox, full)
+        {
+            movement.crouching = false;
+            transform.translation.y += full.half_size.y - hitbox.half_size.y;
+            *hitbox = full;
+        }
+        if !movement.crouching || !on_ground || vel.0.x.abs() < MIN_VEL_PLAYER {
+            movement.sliding = false;
         }
     }
 }
 
+// the player respawns at full height
+fn stand_up_on_death(mut query: Query<(&mut HitBox, &mut Movement)>) {
+    for (mut hitbox, mut movement) in query.iter_mut() {
+        movement.crouching = false;
+        movement.sliding = false;
+        hitbox.half_size = PLAYER_HALF_SIZE;
+    }
+}
+
 pub fn dash_timer(mut query: Query<&mut Movement, With<PlayerMarker>>, time: Res<Time>) {
     let mut movement = query.single_mut();
     if matches!(movement.dash.status, DashState::Dashing) {
@@ -117,39 +389,207 @@ pub fn dash_timer(mut query: Query<&mut Movement, With<PlayerMarker>>, time: Res
     }
 }
 
-impl Solid {
-    pub fn contains(&self, x: f32, y: f32) -> bool {
-        return (self.pos_x - self.bounds.half_size.x) <= x
-            && (self.pos_x + self.bounds.half_size.x) >= x
-            && (self.pos_y - self.bounds.half_size.y <= y)
-            && (self.pos_y + self.bounds.half_size.y >= y);
+// merges single cell colliders of the same kind into maximal rectangles, rows
+// first, then upwards. One-way platforms only merge along x, a taller one would
+// move their top edge
+pub fn merge_colliders(colliders: &[Collider], origin: Vec2, cell_size: Vec2) -> Vec<Collider> {
+    let mut cells: HashMap<IVec2, CollisionKind> = colliders
+        .iter()
+        .map(|item| {
+            let cell = ((item.pos - origin) / cell_size).floor().as_ivec2();
+            (cell, item.kind)
+        })
+        .collect();
+    let mut order: Vec<IVec2> = cells.keys().copied().collect();
+    order.sort_by_key(|cell| (cell.y, cell.x));
+
+    let mut merged = Vec::new();
+    for start in order {
+        // already part of an earlier rectangle
+        let Some(kind) = cells.get(&start).copied() else {
+            continue;
+        };
+        let matches =
+            |cells: &HashMap<IVec2, CollisionKind>, cell: IVec2| cells.get(&cell) == Some(&kind);
+        let mut width = 1;
+        while matches(&cells, start + IVec2::new(width, 0)) {
+            width += 1;
+        }
+        let mut height = 1;
+        if kind != CollisionKind::OneWay {
+            while (0..width).all(|dx| matches(&cells, start + IVec2::new(dx, height))) {
+                height += 1;
+            }
+        }
+        for dy in 0..height {
+            for dx in 0..width {
+                cells.remove(&(start + IVec2::new(dx, dy)));
+            }
+        }
+        let size = IVec2::new(width, height).as_vec2() * cell_size;
+        merged.push(Collider {
+            pos: origin + start.as_vec2() * cell_size + size / 2.,
+            bounds: HitBox {
+                half_size: size / 2.,
+            },
+            kind,
+        });
     }
+    merged
 }
 
-fn contains(solids: &Vec<Solid>, x: f32, y: f32) -> Option<&Solid> {
-    for item in solids {
-        if item.contains(x, y) {
-            return Some(item);
+// sideways shift, up to `max_nudge` pixels, that lets an upward move blocked
+// by a ceiling corner go through. The nearest free side wins
+pub fn ceiling_correction(
+    colliders: &[Collider],
+    translation: Vec3,
+    hitbox: HitBox,
+    move_y: f32,
+    max_nudge: f32,
+) -> Option<f32> {
+    if move_y <= 0. {
+        return None;
+    }
+    let feet_y = translation.y - hitbox.half_size.y;
+    let mut nudge = 1.;
+    while nudge <= max_nudge {
+        for shift in [nudge, -nudge] {
+            let shifted = translation + Vec3::new(shift, 0., 0.);
+            let side_free = side_blocking(colliders, translation, hitbox, shift, feet_y).is_none();
+            let up_free =
+                blocking(colliders, probe_y(shifted, hitbox, move_y), feet_y, move_y).is_none();
+            if side_free && up_free {
+                return Some(shift);
+            }
         }
+        nudge += 1.;
     }
     None
 }
 
-fn update_physics(
-    mut query: Query<
-        (
-            &mut Transform,
-            &mut Velocity,
-            &mut Acceleration,
-            &mut Friction,
-            &mut Gravity,
-            &mut Grounded,
-            &mut Movement,
-        ),
-        With<PlayerMarker>,
-    >,
+// upward lift, up to `max_nudge` pixels, that lets a sideways move blocked by
+// the top edge of a ledge go through
+pub fn ledge_correction(
+    colliders: &[Collider],
+    translation: Vec3,
+    hitbox: HitBox,
+    move_x: f32,
+    max_nudge: f32,
+) -> Option<f32> {
+    if move_x == 0. {
+        return None;
+    }
+    let feet_y = translation.y - hitbox.half_size.y;
+    let mut lift = 1.;
+    while lift <= max_nudge {
+        let lifted = translation + Vec3::new(0., lift, 0.);
+        let head_free =
+            blocking(colliders, probe_y(translation, hitbox, lift), feet_y, lift).is_none();
+        let side_free = side_blocking(colliders, lifted, hitbox, move_x, feet_y + lift).is_none();
+        if head_free && side_free {
+            return Some(lift);
+        }
+        lift += 1.;
+    }
+    None
+}
+
+// point checked against the level when moving `move_x` along x
+pub fn probe_x(translation: Vec3, hitbox: HitBox, move_x: f32) -> Vec2 {
+    Vec2::new(
+        translation.x + move_x + move_x.signum() * hitbox.half_size.x,
+        translation.y,
+    )
+}
+
+pub fn probe_y(translation: Vec3, hitbox: HitBox, move_y: f32) -> Vec2 {
+    Vec2::new(
+        translation.x,
+        translation.y + move_y + move_y.signum() * hitbox.half_size.y,
+    )
+}
+
+// ground right under the feet
+pub fn standing(colliders: &[Collider], translation: Vec3, hitbox: HitBox) -> bool {
+    let feet_y = translation.y - hitbox.half_size.y;
+    blocking(colliders, probe_y(translation, hitbox, -1.), feet_y, -1.).is_some()
+}
+
+// side of a wall within `reach` of the player, -1 left or 1 right
+pub fn wall_side(
+    colliders: &[Collider],
+    translation: Vec3,
+    hitbox: HitBox,
+    reach: f32,
+) -> Option<f32> {
+    let feet_y = translation.y - hitbox.half_size.y;
+    [-reach, reach].into_iter().find_map(|reach| {
+        blocking(colliders, probe_x(translation, hitbox, reach), feet_y, 0.).map(|_| reach.signum())
+    })
+}
+
+// whether the hitbox grown back to `full` around the same feet would overlap
+// something solid
+pub fn can_stand(colliders: &[Collider], translation: Vec3, hitbox: HitBox, full: HitBox) -> bool {
+    let feet_y = translation.y - hitbox.half_size.y;
+    let head = Vec2::new(translation.x, feet_y + full.half_size.y * 2.);
+    blocking(colliders, head, feet_y, 0.).is_none()
+}
+
+// a sideways move is checked at the center and at the top edge, so a standing
+// player does not fit through a gap only a crouch clears
+fn side_blocking(
+    colliders: &[Collider],
+    translation: Vec3,
+    hitbox: HitBox,
+    move_x: f32,
+    feet_y: f32,
+) -> Option<&Collider> {
+    let probe = probe_x(translation, hitbox, move_x);
+    let head = probe + Vec2::new(0., hitbox.half_size.y);
+    blocking(colliders, probe, feet_y, 0.).or_else(|| blocking(colliders, head, feet_y, 0.))
+}
+
+fn blocking(colliders: &[Collider], point: Vec2, feet_y: f32, move_y: f32) -> Option<&Collider> {
+    colliders
+        .iter()
+        .find(|item| item.blocks(point, feet_y, move_y))
+}
+
+// what touching the ground gives back, swimming does the same
+pub fn refill_dash_and_stamina(movement: &mut Movement, stamina: &mut Stamina) {
+    movement.dash.dash_count = DEFAULT_DASH_COUNT;
+    stamina.0 = STAMINA_MAX;
+}
+
+// back to normal movement, also used when a technique cuts a dash short
+fn end_dash(movement: &mut Movement, gravity: &mut Gravity, accel: &mut Acceleration, pos: Vec3) {
+    movement.dash.distance = movement.dash.start_point.distance(pos);
+    movement.dash.status = DashState::Ready;
+    movement.dash.dash_timer.reset();
+    gravity.0 = GRAVITY_DEFAULT;
+    accel.0 = Vec2::ZERO;
+}
+
+type PhysicsQuery<'a> = (
+    &'a mut Transform,
+    &'a mut Velocity,
+    &'a mut Acceleration,
+    &'a mut Friction,
+    &'a mut Gravity,
+    &'a mut Grounded,
+    &'a mut Movement,
+    &'a mut Stamina,
+    &'a HitBox,
+);
+
+pub fn update_physics(
+    mut query: Query<PhysicsQuery, With<PlayerMarker>>,
     level: Res<Level>,
+    config: Res<PhysicsConfig>,
     time: Res<Time>,
+    mut sound_events: EventWriter<SoundEvent>,
+    mut freeze: ResMut<DashFreeze>,
 ) {
     for (
         mut transform,
@@ -159,34 +599,45 @@ fn update_physics(
         mut gravity,
         mut grounded,
         mut movement,
+        mut stamina,
+        hitbox,
     ) in query.iter_mut()
     {
         // CONFIGURATIONS
         match movement.dash.status {
             DashState::Started => {
                 movement.dash.start_point = transform.translation;
-                let temp_vec: Vec2;
-                if movement.directional.abs() == Vec2::ZERO {
-                    temp_vec = vel.0.try_normalize().unwrap_or_else(|| Vec2::new(1., 0.));
-                } else {
-                    temp_vec = movement.directional.normalize();
-                }
+                // without input the dash follows the current velocity
+                let temp_vec = snap_direction(movement.directional)
+                    .or_else(|| snap_direction(vel.0))
+                    .unwrap_or_else(|| Vec2::new(movement.facing, 0.));
                 movement.dash.status = DashState::Cancelled;
-                if !(movement.dash.dash_count > 0) {
+                if movement.dash.dash_count == 0 {
                     return;
                 }
                 movement.dash.status = DashState::Dashing;
                 movement.dash.dash_count -= 1;
-                vel.0 = temp_vec * INITIAL_VEL_DASH;
-                accel.0 = -temp_vec * INITIAL_ACCEL_DASH;
+                movement.dash.direction = temp_vec;
+                freeze.0 = DASH_FREEZE_TIME;
+                sound_events.send(SoundEvent::Dash);
+                let scale = if movement.swimming {
+                    WATER_DASH_SCALE
+                } else {
+                    1.
+                };
+                vel.0 = temp_vec * INITIAL_VEL_DASH * scale;
+                accel.0 = -temp_vec * INITIAL_ACCEL_DASH * scale;
                 gravity.0 = 0.;
                 friction.0 = 0.
             }
             DashState::Finished => {
-                movement.dash.distance = movement.dash.start_point.distance(transform.translation);
-                movement.dash.status = DashState::Ready;
-                gravity.0 = GRAVITY_DEFAULT;
-                accel.0.y = 0.;
+                end_dash(
+                    &mut movement,
+                    &mut gravity,
+                    &mut accel,
+                    transform.translation,
+                );
+                movement.dash.grace = config.dash_jump_grace;
             }
             DashState::Cancelled => {
                 movement.dash.status = DashState::Ready;
@@ -194,19 +645,106 @@ fn update_physics(
             _ => {}
         }
 
+        let dashing = matches!(movement.dash.status, DashState::Dashing);
+        let direction = movement.dash.direction;
+        // a horizontal dash has no vertical move, so `Grounded` alone misses the floor
+        let on_ground = grounded.0 || standing(&level.colliders, transform.translation, *hitbox);
+        if dashing
+            && on_ground
+            && direction.y < 0.
+            && direction.x != 0.
+            && transform.translation.y < movement.dash.start_point.y
+        {
+            // wavedash, a diagonal dash into the ground turns into a slide
+            end_dash(
+                &mut movement,
+                &mut gravity,
+                &mut accel,
+                transform.translation,
+            );
+            movement.dash.grace = config.dash_jump_grace;
+            vel.0 = Vec2::new(
+                direction.x.signum() * MAX_VEL_PLAYER * config.wavedash_speed,
+                0.,
+            );
+        }
+        let fresh_jump = movement.jump && movement.jump_pressed > 0.;
+        if fresh_jump && (dashing || movement.dash.grace > 0.) {
+            // a dash only turns into a wall jump against a touching wall
+            let wall = wall_side(&level.colliders, transform.translation, *hitbox, 1.);
+            let jump = if let (false, Some(side)) = (on_ground, wall) {
+                // dash cancel, kick off away from the wall
+                Some(Vec2::new(
+                    -side * MAX_VEL_PLAYER * config.wall_jump_speed,
+                    JMP_VEL_PLAYER * config.wall_jump_height,
+                ))
+            } else if on_ground && direction.x != 0. {
+                // super jump, long and low while keeping the dash speed
+                let speed = vel.0.x.abs().max(MAX_VEL_PLAYER * config.super_jump_speed);
+                Some(Vec2::new(
+                    direction.x.signum() * speed,
+                    JMP_VEL_PLAYER * config.super_jump_height,
+                ))
+            } else {
+                None
+            };
+            if let Some(jump) = jump {
+                if matches!(movement.dash.status, DashState::Dashing) {
+                    end_dash(
+                        &mut movement,
+                        &mut gravity,
+                        &mut accel,
+                        transform.translation,
+                    );
+                }
+                movement.dash.grace = 0.;
+                movement.jump = false;
+                movement.jump_pressed = 0.;
+                vel.0 = jump;
+                sound_events.send(SoundEvent::Jump);
+            }
+        }
+        movement.dash.grace = (movement.dash.grace - time.delta_seconds()).max(0.);
+
         if !matches!(movement.dash.status, DashState::Dashing) {
             if grounded.0 {
-                movement.dash.dash_count = DEFAULT_DASH_COUNT;
+                refill_dash_and_stamina(&mut movement, &mut stamina);
 
-                if movement.jump {
+                // underwater a jump is kept for leaving at the surface
+                if movement.jump && !movement.swimming {
                     vel.0.y = JMP_VEL_PLAYER;
                     movement.jump = false;
+                    sound_events.send(SoundEvent::Jump);
                 }
             }
 
-            if movement.directional.x.abs() != 0. {
-                accel.0.x = movement.directional.x.signum() * INITIAL_ACCEL_PLAYER;
+            let on_ice = grounded.0
+                && level
+                    .collider_at(probe_y(transform.translation, *hitbox, -1.))
+                    .is_some_and(|item| item.kind == CollisionKind::Ice);
+            // no running while crouched, a slide only keeps its momentum
+            let run_x = if movement.crouching && grounded.0 {
+                0.
+            } else {
+                movement.directional.x
+            };
+            if movement.climbing || movement.grabbing || movement.swimming {
+                // the climb and swim systems drive the velocity directly
+                accel.0.x = 0.;
                 friction.0 = 0.;
+            } else if run_x.abs() != 0. {
+                accel.0.x = run_x.signum() * INITIAL_ACCEL_PLAYER;
+                friction.0 = 0.;
+            } else if movement.swinging && !grounded.0 {
+                // no air drag on the rope, the swing keeps its momentum
+                accel.0.x = 0.;
+                friction.0 = 0.;
+            } else if on_ice {
+                accel.0.x = 0.;
+                friction.0 = vel.0.x.signum() * FRICTION * ICE_FRICTION_SCALE;
+            } else if movement.sliding {
+                accel.0.x = 0.;
+                friction.0 = vel.0.x.signum() * FRICTION * SLIDE_FRICTION_SCALE;
             } else {
                 accel.0.x = 0.;
                 friction.0 = vel.0.x.signum() * FRICTION;
@@ -220,15 +758,18 @@ fn update_physics(
             + (-gravity.0 + accel.0.y) * time.delta_seconds() * time.delta_seconds() * 0.5;
 
         //update velocity and accel
+        let speed = vel.0.x.abs();
         vel.0.x += (accel.0.x - friction.0) * time.delta_seconds();
         if !matches!(movement.dash.status, DashState::Dashing) {
-            vel.0.x = vel.0.x.clamp(-MAX_VEL_PLAYER, MAX_VEL_PLAYER);
+            // speed from dash techniques bleeds off instead of being cut to the run cap
+            let cap = (speed - config.momentum_decay * time.delta_seconds()).max(MAX_VEL_PLAYER);
+            vel.0.x = vel.0.x.clamp(-cap, cap);
         }
         vel.0.y += (-gravity.0 + accel.0.y) * time.delta_seconds();
 
         if vel.0.x >= -MIN_VEL_PLAYER
             && vel.0.x <= MIN_VEL_PLAYER
-            && movement.directional.x.abs() == 0.
+            && (movement.directional.x.abs() == 0. || movement.crouching)
             && !matches!(movement.dash.status, DashState::Dashing)
         {
             vel.0.x = 0.;
@@ -236,15 +777,6 @@ fn update_physics(
             friction.0 = 0.;
         }
 
-        info!(
-            "accel_x : {} : vel_x : {} : friction : {} : time : {} : status : {:?}",
-            accel.0.x,
-            vel.0.x,
-            friction.0,
-            time.delta_seconds(),
-            movement.directional
-        );
-
         // info!(
         //     "accel_y : {} : vel_y : {} : gravity : {} : time : {} : status : {:?}",
         //     accel.0.y,
@@ -266,30 +798,27 @@ fn update_physics(
         // );
 
         // CHECK MOVE X --------------------------------
-        if contains(
-            &level.grid,
-            transform.translation.x + move_x + move_x.signum() * BLOCK_SIZE.x * 0.5,
-            transform.translation.y,
+        // one-way platforms compare against where the feet were before moving
+        let feet_y = transform.translation.y - hitbox.half_size.y;
+        if side_blocking(
+            &level.colliders,
+            transform.translation,
+            *hitbox,
+            move_x,
+            feet_y,
         )
         .is_none()
         {
-            // if !contains(
-            //     &level.grid,
-            //     transform.translation.x + move_x + move_x.signum() * BLOCK_SIZE.x * 0.5,
-            //     transform.translation.y - (BLOCK_SIZE.y * 0.5),
-            // )
-            // .is_none()
-            // {
-            //     transform.translation.y += BLOCK_SIZE.y * 0.5;
-            // } else if !contains(
-            //     &level.grid,
-            //     transform.translation.x + move_x + move_x.signum() * BLOCK_SIZE.x * 0.5,
-            //     transform.translation.y + (BLOCK_SIZE.y * 0.5),
-            // )
-            // .is_none()
-            // {
-            //     transform.translation.y -= BLOCK_SIZE.y * 0.5;
-            // }
+            transform.translation.x += move_x;
+        } else if let Some(lift) = ledge_correction(
+            &level.colliders,
+            transform.translation,
+            *hitbox,
+            move_x,
+            config.ledge_assist,
+        ) {
+            // barely missed a ledge, pop up onto it and keep going
+            transform.translation.y += lift;
             transform.translation.x += move_x;
         } else {
             // vel.0.x = 0.;
@@ -309,36 +838,636 @@ fn update_physics(
         // );
 
         // CHECK MOVE Y --------------------------------
-        if contains(
-            &level.grid,
-            transform.translation.x,
-            transform.translation.y + move_y + move_y.signum() * BLOCK_SIZE.y * 0.5,
-        )
-        .is_none()
-        {
-            // if !contains(
-            //     &level.grid,
-            //     transform.translation.x - (BLOCK_SIZE.x * 0.5),
-            //     transform.translation.y + move_y + move_y.signum() * BLOCK_SIZE.y * 0.5,
-            // )
-            // .is_none()
-            // {
-            //     transform.translation.x += BLOCK_SIZE.x * 0.5;
-            // } else if !contains(
-            //     &level.grid,
-            //     transform.translation.x + (BLOCK_SIZE.x * 0.5),
-            //     transform.translation.y + move_y + move_y.signum() * BLOCK_SIZE.y * 0.5,
-            // )
-            // .is_none()
-            // {
-            //     transform.translation.x -= BLOCK_SIZE.x * 0.5;
-            // }
+        let probe = probe_y(transform.translation, *hitbox, move_y);
+        if blocking(&level.colliders, probe, feet_y, move_y).is_none() {
+            transform.translation.y += move_y;
+            grounded.0 = false;
+        } else if let Some(shift) = ceiling_correction(
+            &level.colliders,
+            transform.translation,
+            *hitbox,
+            move_y,
+            config.corner_correction,
+        ) {
+            // head grazed a ceiling corner, slide around it
+            transform.translation.x += shift;
             transform.translation.y += move_y;
             grounded.0 = false;
         } else {
             // vel.0.y = 0.;
             // accel.0.y = 0.;
+            if !grounded.0 && move_y < 0. {
+                sound_events.send(SoundEvent::Land);
+            }
             grounded.0 = true;
         }
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    const CELL: Vec2 = Vec2::splat(16.);
+    const PLAYER: HitBox = HitBox {
+        half_size: PLAYER_HALF_SIZE,
+    };
+
+    // rows are listed top first, '.' is empty
+    pub(crate) fn grid(rows: &[&str]) -> Vec<Collider> {
+        let mut colliders = Vec::new();
+        for (y, row) in rows.iter().rev().enumerate() {
+            for (x, cell) in row.chars().enumerate() {
+                let kind = match cell {
+                    '#' => CollisionKind::Solid,
+                    '-' => CollisionKind::OneWay,
+                    '^' => CollisionKind::Hazard,
+                    '~' => CollisionKind::Water,
+                    'i' => CollisionKind::Ice,
+                    'H' => CollisionKind::Climbable,
+                    'b' => CollisionKind::Breakable,
+                    _ => continue,
+                };
+                colliders.push(Collider {
+                    pos: Vec2::new(x as f32, y as f32) * CELL + CELL / 2.,
+                    bounds: HitBox {
+                        half_size: CELL / 2.,
+                    },
+                    kind,
+                });
+            }
+        }
+        colliders
+    }
+
+    fn kind_at(colliders: &[Collider], point: Vec2) -> Option<CollisionKind> {
+        colliders
+            .iter()
+            .find(|item| item.contains(point))
+            .map(|item| item.kind)
+    }
+
+    // samples every `step` pixels starting at `offset` over the whole grid
+    fn assert_same_points(colliders: &[Collider], step: f32, offset: f32) {
+        let merged = merge_colliders(colliders, Vec2::ZERO, CELL);
+        let max = colliders
+            .iter()
+            .map(|item| item.pos + item.bounds.half_size)
+            .fold(Vec2::ZERO, Vec2::max);
+        let mut y = -CELL.y + offset;
+        while y <= max.y + CELL.y {
+            let mut x = -CELL.x + offset;
+            while x <= max.x + CELL.x {
+                let point = Vec2::new(x, y);
+                assert_eq!(
+                    kind_at(colliders, point),
+                    kind_at(&merged, point),
+                    "at {:?}",
+                    point
+                );
+                x += step;
+            }
+            y += step;
+        }
+    }
+
+    #[test]
+    fn long_floor_is_one_rectangle() {
+        let colliders = grid(&["##########"]);
+        let merged = merge_colliders(&colliders, Vec2::ZERO, CELL);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].pos, Vec2::new(80., 8.));
+        assert_eq!(merged[0].bounds.half_size, Vec2::new(80., 8.));
+    }
+
+    #[test]
+    fn blocks_merge_in_both_directions() {
+        let colliders = grid(&["####", "####", "####"]);
+        assert_eq!(merge_colliders(&colliders, Vec2::ZERO, CELL).len(), 1);
+    }
+
+    #[test]
+    fn one_way_platforms_only_merge_along_x() {
+        let colliders = grid(&["---", "---"]);
+        let merged = merge_colliders(&colliders, Vec2::ZERO, CELL);
+        assert_eq!(merged.len(), 2);
+        assert!(merged
+            .iter()
+            .all(|item| item.bounds.half_size == Vec2::new(24., 8.)));
+    }
+
+    #[test]
+    fn kinds_are_merged_separately() {
+        let colliders = grid(&["##ii##", "######"]);
+        let merged = merge_colliders(&colliders, Vec2::ZERO, CELL);
+        assert_eq!(
+            merged
+                .iter()
+                .filter(|item| item.kind == CollisionKind::Ice)
+                .count(),
+            1
+        );
+        assert_same_points(&colliders, 2., 1.);
+    }
+
+    #[test]
+    fn merged_level_agrees_with_tiles() {
+        let colliders = grid(&[
+            "################",
+            "#..............#",
+            "#...---....~~~.#",
+            "#..........~~~.#",
+            "#.####.....####.",
+            "#.#..#..........",
+            "#.####...^^^^..#",
+            "#iiiiii#########",
+        ]);
+        // points off the cell edges, where two kinds can touch
+        assert_same_points(&colliders, 2., 1.);
+    }
+
+    #[test]
+    fn seams_and_edges_agree_for_one_kind() {
+        let colliders = grid(&["#####.", "##.###", "######", "..##.."]);
+        // steps of 4 from 0 also land on every internal seam and corner
+        assert_same_points(&colliders, 4., 0.);
+    }
+
+    #[test]
+    fn random_grids_agree() {
+        let kinds = ['#', '#', '-', '^', '~', 'i', '.', '.'];
+        let mut seed: u32 = 12345;
+        for _ in 0..20 {
+            let rows: Vec<String> = (0..10)
+                .map(|_| {
+                    (0..12)
+                        .map(|_| {
+                            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                            kinds[(seed >> 16) as usize % kinds.len()]
+                        })
+                        .collect()
+                })
+                .collect();
+            let rows: Vec<&str> = rows.iter().map(|row| row.as_str()).collect();
+            assert_same_points(&grid(&rows), 2., 1.);
+        }
+    }
+
+    #[test]
+    fn offset_origin_keeps_positions() {
+        let origin = Vec2::new(-40., 100.);
+        let colliders: Vec<Collider> = grid(&["###", "#.#"])
+            .into_iter()
+            .map(|mut item| {
+                item.pos += origin;
+                item
+            })
+            .collect();
+        let merged = merge_colliders(&colliders, origin, CELL);
+        for item in &colliders {
+            assert_eq!(kind_at(&merged, item.pos), Some(item.kind));
+        }
+        assert_eq!(kind_at(&merged, origin + Vec2::new(24., 8.)), None);
+    }
+
+    pub(crate) fn test_level(rows: &[&str]) -> Level {
+        Level {
+            world_pos: Vec3::ZERO,
+            block_size: CELL,
+            layers: Vec::new(),
+            grid: Vec::new(),
+            colliders: grid(rows),
+            player_spawn_pos: Vec2::ZERO,
+            music_file: None,
+            name: "test".to_string(),
+            entities: Vec::new(),
+        }
+    }
+
+    // a world with just what update_physics needs, stepped by hand
+    pub(crate) fn physics_world(
+        level: Level,
+        config: PhysicsConfig,
+        pos: Vec2,
+        vel: Vec2,
+    ) -> World {
+        let mut world = World::new();
+        world.insert_resource(level);
+        world.insert_resource(config);
+        world.init_resource::<DashFreeze>();
+        world.insert_resource(Time::<()>::default());
+        world.init_resource::<Events<SoundEvent>>();
+        world.spawn((
+            Transform::from_translation(pos.extend(0.)),
+            Velocity(vel),
+            Acceleration::default(),
+            Friction(0.),
+            Gravity::default(),
+            Grounded(false),
+            Movement::default(),
+            Stamina::default(),
+            PLAYER,
+            PlayerMarker,
+        ));
+        world
+    }
+
+    pub(crate) fn step(world: &mut World, ticks: usize) {
+        for _ in 0..ticks {
+            world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs_f32(1. / 60.));
+            world.run_system_once(update_physics);
+        }
+    }
+
+    pub(crate) fn player_pos(world: &mut World) -> Vec2 {
+        world
+            .query_filtered::<&Transform, With<PlayerMarker>>()
+            .single(world)
+            .translation
+            .truncate()
+    }
+
+    fn hold_right(world: &mut World) {
+        let mut query = world.query::<(&mut Movement, &mut Gravity)>();
+        for (mut movement, mut gravity) in query.iter_mut(world) {
+            movement.directional = Vec2::X;
+            gravity.0 = 0.;
+        }
+    }
+
+    // a single ceiling tile spanning x 0..16 and y 64..80
+    const CEILING: [&str; 5] = ["#...", "....", "....", "....", "...."];
+
+    #[test]
+    fn jump_slides_around_a_grazed_ceiling_corner() {
+        let level = test_level(&CEILING);
+        let jump = Vec2::new(0., JMP_VEL_PLAYER);
+        let mut world = physics_world(level, PhysicsConfig::default(), Vec2::new(14., 40.), jump);
+        step(&mut world, 20);
+        let pos = player_pos(&mut world);
+        assert!(pos.x > 16., "not nudged past the corner : {:?}", pos);
+        assert!(pos.y > 56., "still under the ceiling : {:?}", pos);
+    }
+
+    #[test]
+    fn jump_deep_under_a_ceiling_is_blocked() {
+        let level = test_level(&CEILING);
+        let jump = Vec2::new(0., JMP_VEL_PLAYER);
+        let mut world = physics_world(level, PhysicsConfig::default(), Vec2::new(10., 40.), jump);
+        step(&mut world, 20);
+        let pos = player_pos(&mut world);
+        assert!(pos.x < 16., "nudged too far : {:?}", pos);
+        assert!(pos.y <= 56., "went through the ceiling : {:?}", pos);
+    }
+
+    #[test]
+    fn corner_correction_can_be_disabled() {
+        let level = test_level(&CEILING);
+        let config = PhysicsConfig {
+            corner_correction: 0.,
+            ..default()
+        };
+        let jump = Vec2::new(0., JMP_VEL_PLAYER);
+        let mut world = physics_world(level, config, Vec2::new(14., 40.), jump);
+        step(&mut world, 20);
+        let pos = player_pos(&mut world);
+        assert!(pos.x < 16.);
+        assert!(pos.y <= 56.);
+    }
+
+    // a ledge spanning x 48..64 with its top at y 32
+    const LEDGE: [&str; 2] = ["...#", "...#"];
+
+    #[test]
+    fn run_pops_onto_a_barely_missed_ledge() {
+        let level = test_level(&LEDGE);
+        let run = Vec2::new(MAX_VEL_PLAYER, 0.);
+        let mut world = physics_world(level, PhysicsConfig::default(), Vec2::new(30., 30.), run);
+        hold_right(&mut world);
+        step(&mut world, 20);
+        let pos = player_pos(&mut world);
+        assert!(pos.x > 48., "stopped at the ledge : {:?}", pos);
+        assert!(pos.y > 32. && pos.y <= 34., "lifted too far : {:?}", pos);
+    }
+
+    #[test]
+    fn run_into_a_wall_is_blocked() {
+        let level = test_level(&LEDGE);
+        let run = Vec2::new(MAX_VEL_PLAYER, 0.);
+        let mut world = physics_world(level, PhysicsConfig::default(), Vec2::new(30., 26.), run);
+        hold_right(&mut world);
+        step(&mut world, 20);
+        let pos = player_pos(&mut world);
+        assert!(pos.x < 40., "went through the wall : {:?}", pos);
+        assert_eq!(pos.y, 26.);
+    }
+
+    #[test]
+    fn ledge_assist_can_be_disabled() {
+        let level = test_level(&LEDGE);
+        let config = PhysicsConfig {
+            ledge_assist: 0.,
+            ..default()
+        };
+        let run = Vec2::new(MAX_VEL_PLAYER, 0.);
+        let mut world = physics_world(level, config, Vec2::new(30., 30.), run);
+        hold_right(&mut world);
+        step(&mut world, 20);
+        assert!(player_pos(&mut world).x < 40.);
+    }
+
+    fn start_dash(world: &mut World, direction: Vec2) {
+        let mut query = world.query::<&mut Movement>();
+        for mut movement in query.iter_mut(world) {
+            movement.directional = direction;
+            movement.dash.status = DashState::Started;
+        }
+    }
+
+    pub(crate) fn press_jump(world: &mut World) {
+        let mut query = world.query::<&mut Movement>();
+        for mut movement in query.iter_mut(world) {
+            movement.jump = true;
+            movement.jump_pressed = JUMP_PRESS_WINDOW;
+        }
+    }
+
+    pub(crate) fn player_vel(world: &mut World) -> Vec2 {
+        world
+            .query_filtered::<&Velocity, With<PlayerMarker>>()
+            .single(world)
+            .0
+    }
+
+    const FLOOR: [&str; 2] = ["........", "########"];
+
+    #[test]
+    fn jump_out_of_a_ground_dash_is_a_super_jump() {
+        let level = test_level(&FLOOR);
+        let mut world = physics_world(
+            level,
+            PhysicsConfig::default(),
+            Vec2::new(24., 24.),
+            Vec2::ZERO,
+        );
+        step(&mut world, 1);
+        start_dash(&mut world, Vec2::X);
+        step(&mut world, 2);
+        let dash_speed = player_vel(&mut world).x;
+        assert!(dash_speed > MAX_VEL_PLAYER * SUPER_JUMP_SPEED);
+        press_jump(&mut world);
+        step(&mut world, 1);
+        let vel = player_vel(&mut world);
+        assert!(vel.x > dash_speed * 0.9, "lost the dash speed : {:?}", vel);
+        assert!(vel.y > 0.);
+        // the extra speed bleeds off instead of being cut off
+        step(&mut world, 5);
+        assert!(player_vel(&mut world).x > MAX_VEL_PLAYER);
+    }
+
+    #[test]
+    fn diagonal_dash_into_the_ground_is_a_wavedash() {
+        let level = test_level(&FLOOR);
+        let mut world = physics_world(
+            level,
+            PhysicsConfig::default(),
+            Vec2::new(24., 48.),
+            Vec2::ZERO,
+        );
+        start_dash(&mut world, Vec2::new(1., -1.));
+        step(&mut world, 8);
+        let vel = player_vel(&mut world);
+        assert!(vel.x > MAX_VEL_PLAYER * 1.5, "no wavedash : {:?}", vel);
+        let movement = world.query::<&Movement>().single(&world);
+        assert!(matches!(movement.dash.status, DashState::Ready));
+    }
+
+    #[test]
+    fn jump_next_to_a_wall_cancels_the_dash() {
+        let level = test_level(&["....#", "....#", "....#", "....#", "....."]);
+        let mut world = physics_world(
+            level,
+            PhysicsConfig::default(),
+            Vec2::new(56., 40.),
+            Vec2::ZERO,
+        );
+        start_dash(&mut world, Vec2::Y);
+        step(&mut world, 1);
+        press_jump(&mut world);
+        step(&mut world, 1);
+        let vel = player_vel(&mut world);
+        assert!(vel.x < 0., "did not kick off the wall : {:?}", vel);
+        let movement = world.query::<&Movement>().single(&world);
+        assert!(matches!(movement.dash.status, DashState::Ready));
+    }
+
+    #[test]
+    fn jump_held_from_before_the_dash_is_not_a_dash_jump() {
+        let level = test_level(&FLOOR);
+        let mut world = physics_world(
+            level,
+            PhysicsConfig::default(),
+            Vec2::new(24., 24.),
+            Vec2::ZERO,
+        );
+        step(&mut world, 1);
+        press_jump(&mut world);
+        // the press is too old by the time the dash starts
+        let mut query = world.query::<&mut Movement>();
+        query.single_mut(&mut world).jump_pressed = 0.;
+        start_dash(&mut world, Vec2::X);
+        step(&mut world, 2);
+        let movement = world.query::<&Movement>().single(&world);
+        assert!(matches!(movement.dash.status, DashState::Dashing));
+        assert!(player_vel(&mut world).y <= 0.);
+    }
+
+    fn step_crouch(world: &mut World, ticks: usize) {
+        for _ in 0..ticks {
+            world.run_system_once(crouch);
+            step(world, 1);
+        }
+    }
+
+    pub(crate) fn hold(world: &mut World, directional: Vec2) {
+        let mut query = world.query::<&mut Movement>();
+        for mut movement in query.iter_mut(world) {
+            movement.directional = directional;
+        }
+    }
+
+    #[test]
+    fn death_stands_a_crouched_player_up() {
+        let level = test_level(&FLOOR);
+        let mut world = physics_world(
+            level,
+            PhysicsConfig::default(),
+            Vec2::new(24., 24.),
+            Vec2::ZERO,
+        );
+        step(&mut world, 1);
+        hold(&mut world, Vec2::NEG_Y);
+        step_crouch(&mut world, 2);
+        assert!(world.query::<&Movement>().single(&world).crouching);
+        world.run_system_once(stand_up_on_death);
+        let (movement, hitbox) = world.query::<(&Movement, &HitBox)>().single(&world);
+        assert!(!movement.crouching && !movement.sliding);
+        assert_eq!(hitbox.half_size, PLAYER_HALF_SIZE);
+    }
+
+    // a one tile high tunnel from x 48 on, the floor top is at y 16
+    const TUNNEL: [&str; 3] = ["...#####", "........", "########"];
+
+    #[test]
+    fn standing_player_does_not_fit_a_one_tile_gap() {
+        let level = test_level(&TUNNEL);
+        let mut world = physics_world(
+            level,
+            PhysicsConfig::default(),
+            Vec2::new(24., 24.),
+            Vec2::ZERO,
+        );
+        hold(&mut world, Vec2::X);
+        step_crouch(&mut world, 30);
+        assert!(player_pos(&mut world).x < 48.);
+    }
+
+    #[test]
+    fn crouched_player_fits_a_one_tile_gap_and_stays_down() {
+        let level = test_level(&TUNNEL);
+        let run = Vec2::new(MAX_VEL_PLAYER, 0.);
+        let mut world = physics_world(level, PhysicsConfig::default(), Vec2::new(24., 24.), run);
+        step_crouch(&mut world, 2);
+        hold(&mut world, Vec2::new(1., -1.));
+        step_crouch(&mut world, 20);
+        let pos = player_pos(&mut world);
+        assert!(pos.x > 56., "slide stopped before the tunnel : {:?}", pos);
+        // letting go under the ceiling keeps the crouch
+        hold(&mut world, Vec2::ZERO);
+        step_crouch(&mut world, 2);
+        let hitbox = *world.query::<&HitBox>().single(&world);
+        assert_eq!(hitbox.half_size.y, CROUCH_HALF_HEIGHT);
+    }
+
+    #[test]
+    fn crouch_keeps_the_feet_in_place() {
+        let level = test_level(&FLOOR);
+        let mut world = physics_world(
+            level,
+            PhysicsConfig::default(),
+            Vec2::new(24., 24.),
+            Vec2::ZERO,
+        );
+        step_crouch(&mut world, 2);
+        let feet = player_pos(&mut world).y - PLAYER_HALF_SIZE.y;
+        hold(&mut world, Vec2::NEG_Y);
+        step_crouch(&mut world, 2);
+        assert_eq!(player_pos(&mut world).y - CROUCH_HALF_HEIGHT, feet);
+        hold(&mut world, Vec2::ZERO);
+        step_crouch(&mut world, 2);
+        assert_eq!(player_pos(&mut world).y - PLAYER_HALF_SIZE.y, feet);
+    }
+
+    #[test]
+    fn dash_directions_snap_to_eight_ways() {
+        let snapped = |x: f32, y: f32| snap_direction(Vec2::new(x, y)).unwrap();
+        assert!(snapped(1., 0.1).abs_diff_eq(Vec2::X, 1e-5));
+        assert!(snapped(0.9, 0.8).abs_diff_eq(Vec2::ONE.normalize(), 1e-5));
+        assert!(snapped(-0.2, -1.).abs_diff_eq(Vec2::NEG_Y, 1e-5));
+        assert!(snapped(-1., 0.9).abs_diff_eq(Vec2::new(-1., 1.).normalize(), 1e-5));
+        assert_eq!(snap_direction(Vec2::ZERO), None);
+    }
+
+    #[test]
+    fn dash_without_input_follows_the_velocity() {
+        let level = test_level(&["........", "........", "........", "........"]);
+        let mut world = physics_world(
+            level,
+            PhysicsConfig::default(),
+            Vec2::new(64., 32.),
+            Vec2::new(-40., 30.),
+        );
+        start_dash(&mut world, Vec2::ZERO);
+        step(&mut world, 1);
+        let movement = world.query::<&Movement>().single(&world);
+        assert!(matches!(movement.dash.status, DashState::Dashing));
+        // snapped to the nearest of the eight directions like a held one
+        assert!(movement
+            .dash
+            .direction
+            .abs_diff_eq(Vec2::new(-1., 1.).normalize(), 1e-5));
+    }
+
+    #[test]
+    fn wall_side_only_looks_as_far_as_its_reach() {
+        // a wall over x 64..80
+        let colliders = grid(&["....#", "....#"]);
+        let touching = Vec3::new(56., 16., 0.);
+        let near = Vec3::new(54., 16., 0.);
+        assert_eq!(wall_side(&colliders, touching, PLAYER, 1.), Some(1.));
+        assert_eq!(wall_side(&colliders, near, PLAYER, 1.), None);
+        assert_eq!(wall_side(&colliders, near, PLAYER, GRAB_REACH), Some(1.));
+    }
+
+    #[test]
+    fn ceiling_correction_prefers_the_nearest_side() {
+        // one ceiling tile spanning x 48..64 and y 32..48
+        let colliders = grid(&["...#...", ".......", "......."]);
+        let left = ceiling_correction(&colliders, Vec3::new(50., 20., 0.), PLAYER, 10., 4.);
+        let right = ceiling_correction(&colliders, Vec3::new(62., 20., 0.), PLAYER, 10., 4.);
+        assert_eq!(left, Some(-3.));
+        assert_eq!(right, Some(3.));
+        // only upward moves are corrected
+        assert_eq!(
+            ceiling_correction(&colliders, Vec3::new(50., 20., 0.), PLAYER, -10., 4.),
+            None
+        );
+    }
+
+    fn start_swimming(world: &mut World) {
+        let mut query = world.query::<(&mut Movement, &mut Gravity)>();
+        for (mut movement, mut gravity) in query.iter_mut(world) {
+            movement.swimming = true;
+            gravity.0 = 0.;
+        }
+    }
+
+    #[test]
+    fn dash_underwater_is_weaker() {
+        let level = test_level(&["~~~~~~~~", "~~~~~~~~"]);
+        let mut world = physics_world(
+            level,
+            PhysicsConfig::default(),
+            Vec2::new(24., 16.),
+            Vec2::ZERO,
+        );
+        start_swimming(&mut world);
+        start_dash(&mut world, Vec2::X);
+        step(&mut world, 1);
+        assert_eq!(
+            player_vel(&mut world).x,
+            INITIAL_VEL_DASH * WATER_DASH_SCALE - INITIAL_ACCEL_DASH * WATER_DASH_SCALE / 60.
+        );
+    }
+
+    #[test]
+    fn jump_on_the_water_floor_is_kept_for_the_surface() {
+        let level = test_level(&["~~~~~~~~", "~~~~~~~~", "########"]);
+        let mut world = physics_world(
+            level,
+            PhysicsConfig::default(),
+            Vec2::new(24., 24.),
+            Vec2::ZERO,
+        );
+        start_swimming(&mut world);
+        step(&mut world, 1);
+        press_jump(&mut world);
+        step(&mut world, 1);
+        assert_eq!(player_vel(&mut world).y, 0.);
+        assert!(world.query::<&Movement>().single(&world).jump);
+    }
+}
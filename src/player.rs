@@ -1,26 +1,78 @@
 use std::time::Duration;
 
 use crate::{
-    level::Level,
-    physics::{Acceleration, DashState, Friction, Gravity, Grounded, Movement, Velocity},
+    audio::SoundEvent,
+    entities::{LdtkEntity, RegisterLdtkEntity},
+    level::{Level, LevelStats},
+    physics::{
+        Acceleration, CollisionKind, DashState, Friction, Gravity, Grounded, Movement, PhysicsSet,
+        Stamina, Velocity,
+    },
+    state::{GameState, LevelEntity},
+    util::{
+        HitBox, DASH_BUFFER_TIME, JUMP_PRESS_WINDOW, KILL_PLANE_MARGIN, PLAYER_HALF_SIZE,
+        PLAYER_TEXTURE, STICK_DEADZONE,
+    },
 };
-use bevy::{math::Vec2, prelude::*};
+use bevy::{ecs::system::EntityCommands, math::Vec2, prelude::*};
 pub struct PlayerPlugin;
 #[derive(Component)]
 pub struct Player;
 
 #[derive(Component)]
 pub struct PlayerMarker;
+
+#[derive(Event)]
+pub struct PlayerDeath;
+
+const GAMEPAD_JUMP: GamepadButtonType = GamepadButtonType::South;
+const GAMEPAD_DASH: GamepadButtonType = GamepadButtonType::West;
+const GAMEPAD_GRAB: GamepadButtonType = GamepadButtonType::RightTrigger2;
+const GAMEPAD_GRAPPLE: GamepadButtonType = GamepadButtonType::LeftTrigger2;
+
+#[derive(Resource, Clone, Copy, Debug, PartialEq)]
+pub struct KeyBindings {
+    pub up: KeyCode,
+    pub down: KeyCode,
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub jump: KeyCode,
+    pub dash: KeyCode,
+    pub grab: KeyCode,
+    pub grapple: KeyCode,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            up: KeyCode::ArrowUp,
+            down: KeyCode::ArrowDown,
+            left: KeyCode::ArrowLeft,
+            right: KeyCode::ArrowRight,
+            jump: KeyCode::Space,
+            dash: KeyCode::KeyQ,
+            grab: KeyCode::ShiftLeft,
+            grapple: KeyCode::KeyE,
+        }
+    }
+}
+
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, spawn_player).add_systems(
+        app.init_resource::<KeyBindings>()
+            .add_event::<PlayerDeath>();
+        app.register_ldtk_entity("PlayerSpawnLocation", spawn_player);
+        app.add_systems(
             Update,
             (
-                control_player,
+                control_player.before(PhysicsSet::PreStep),
                 draw_trail_while_dashing.after(control_player),
                 update_particle_timer.after(draw_trail_while_dashing),
                 afterimage_while_dashing.after(draw_trail_while_dashing),
-            ),
+                respawn_on_death,
+                fit_sprite_to_hitbox,
+            )
+                .run_if(in_state(GameState::Playing)),
         );
     }
 }
@@ -44,13 +96,26 @@ fn draw_trail_while_dashing(
                 color: Color::WHITE,
                 ..default()
             },
-            transform: transform.clone(),
+            transform: *transform,
             ..default()
         },
         TrailParticle(Timer::new(Duration::from_secs_f32(0.5), TimerMode::Once)),
+        LevelEntity,
     ));
 }
 
+// translucent copy of the player sprite, also used for ghost replays
+pub fn afterimage_bundle(asset_server: &AssetServer, transform: Transform) -> SpriteBundle {
+    let mut bundle = SpriteBundle {
+        texture: asset_server.load(PLAYER_TEXTURE),
+        ..default()
+    };
+
+    bundle.sprite.color = bundle.sprite.color.with_a(0.5);
+    bundle.transform = transform;
+    bundle
+}
+
 fn afterimage_while_dashing(
     mut commands: Commands,
     query: Query<(&Transform, &Movement), With<PlayerMarker>>,
@@ -60,13 +125,7 @@ fn afterimage_while_dashing(
     mut local_translation: Local<Vec3>,
 ) {
     let (transform, movement) = query.single();
-    let mut bundle = SpriteBundle {
-        texture: asset_server.load("tile_0022.png"),
-        ..default()
-    };
-
-    bundle.sprite.color = bundle.sprite.color.with_a(0.5);
-    bundle.transform = transform.clone();
+    let bundle = afterimage_bundle(&asset_server, *transform);
     *local_dist += (*local_translation).distance(transform.translation);
     *local_translation = transform.translation;
     if matches!(movement.dash.status, DashState::Dashing) && *local_dist >= 100. / 4. {
@@ -74,6 +133,7 @@ fn afterimage_while_dashing(
         commands.spawn((
             bundle,
             TrailParticle(Timer::new(Duration::from_secs_f32(0.2), TimerMode::Once)),
+            LevelEntity,
         ));
     }
 }
@@ -85,24 +145,20 @@ fn update_particle_timer(
 ) {
     for (entity, mut particle, mut sprite) in query.iter_mut() {
         particle.0.tick(time.delta());
-        sprite.color = Color::rgba(1., 1., 1., sprite.color.a() * 0.99);
+        sprite.color = sprite.color.with_a(sprite.color.a() * 0.99);
         if particle.0.just_finished() {
             commands.entity(entity).despawn();
         }
     }
 }
 
-fn spawn_player(mut commands: Commands, asset_server: Res<AssetServer>, level: Res<Level>) {
-    commands.spawn((
-        SpriteBundle {
-            texture: asset_server.load("tile_0022.png"),
-            transform: Transform::from_translation(Vec3::new(
-                level.player_spawn_pos.x,
-                level.player_spawn_pos.y,
-                0.,
-            )),
-            ..default()
-        },
+fn spawn_player(entity: &mut EntityCommands, item: &LdtkEntity, asset_server: &AssetServer) {
+    // same corner placement as Level::player_spawn_pos, used for respawns
+    let spawn_pos = item.pos - item.size / 2.;
+    entity.insert((
+        Transform::from_translation(spawn_pos.extend(0.)),
+        asset_server.load::<Image>(PLAYER_TEXTURE),
+        Sprite::default(),
         Player,
         Velocity::default(),
         Acceleration::default(),
@@ -111,35 +167,140 @@ fn spawn_player(mut commands: Commands, asset_server: Res<AssetServer>, level: R
         Friction(0.),
         Grounded(false),
         Movement::default(),
+        Stamina::default(),
+        HitBox {
+            half_size: PLAYER_HALF_SIZE,
+        },
     ));
 }
 
+// the sprite squashes with the hitbox while crouching
+fn fit_sprite_to_hitbox(mut query: Query<(&HitBox, &mut Sprite), Changed<HitBox>>) {
+    for (hitbox, mut sprite) in query.iter_mut() {
+        sprite.custom_size = Some(hitbox.half_size * 2.);
+    }
+}
+
+// left stick with a radial deadzone, rescaled so it still reaches full length
+fn stick_direction(gamepads: &Gamepads, axes: &Axis<GamepadAxis>) -> Vec2 {
+    for gamepad in gamepads.iter() {
+        let axis = |axis_type| axes.get(GamepadAxis::new(gamepad, axis_type)).unwrap_or(0.);
+        let stick = Vec2::new(
+            axis(GamepadAxisType::LeftStickX),
+            axis(GamepadAxisType::LeftStickY),
+        );
+        let length = stick.length();
+        if length > STICK_DEADZONE {
+            return stick / length * ((length - STICK_DEADZONE) / (1. - STICK_DEADZONE)).min(1.);
+        }
+    }
+    Vec2::ZERO
+}
+
 fn control_player(
     input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+    time: Res<Time>,
     mut query: Query<&mut Movement, With<PlayerMarker>>,
 ) {
+    let pad_pressed = |button_type| {
+        gamepads
+            .iter()
+            .any(|gamepad| buttons.just_pressed(GamepadButton::new(gamepad, button_type)))
+    };
+    let pad_released = |button_type| {
+        gamepads
+            .iter()
+            .any(|gamepad| buttons.just_released(GamepadButton::new(gamepad, button_type)))
+    };
     let mut temp_vec = Vec2::ZERO;
     let mut movement = query.single_mut();
-    if input.just_pressed(KeyCode::Space) {
+    if input.just_pressed(bindings.jump) || pad_pressed(GAMEPAD_JUMP) {
         movement.jump = true;
+        movement.jump_pressed = JUMP_PRESS_WINDOW;
     }
-    if input.just_released(KeyCode::Space) {
+    movement.jump_pressed = (movement.jump_pressed - time.delta_seconds()).max(0.);
+    if input.just_released(bindings.jump) || pad_released(GAMEPAD_JUMP) {
         movement.jump = false;
     }
-    if input.pressed(KeyCode::ArrowUp) {
+    let pad_held = |button_type| {
+        gamepads
+            .iter()
+            .any(|gamepad| buttons.pressed(GamepadButton::new(gamepad, button_type)))
+    };
+    movement.jump_held = input.pressed(bindings.jump) || pad_held(GAMEPAD_JUMP);
+    movement.grab_held = input.pressed(bindings.grab) || pad_held(GAMEPAD_GRAB);
+    movement.grapple_held = input.pressed(bindings.grapple) || pad_held(GAMEPAD_GRAPPLE);
+    movement.grapple = input.just_pressed(bindings.grapple) || pad_pressed(GAMEPAD_GRAPPLE);
+    if input.pressed(bindings.up) {
         temp_vec += Vec2::new(-0., 1.);
     }
-    if input.pressed(KeyCode::ArrowDown) {
+    if input.pressed(bindings.down) {
         temp_vec += Vec2::new(0., -1.);
     }
-    if input.pressed(KeyCode::ArrowLeft) {
+    if input.pressed(bindings.left) {
         temp_vec += Vec2::new(-1., 0.);
     }
-    if input.pressed(KeyCode::ArrowRight) {
+    if input.pressed(bindings.right) {
         temp_vec += Vec2::new(1., 0.);
     }
-    if input.just_pressed(KeyCode::KeyQ) && matches!(movement.dash.status, DashState::Ready) {
+    if temp_vec == Vec2::ZERO {
+        temp_vec = stick_direction(&gamepads, &axes);
+    }
+
+    // a press that comes a little early still dashes once the dash is ready
+    if input.just_pressed(bindings.dash) || pad_pressed(GAMEPAD_DASH) {
+        movement.dash.buffered = DASH_BUFFER_TIME;
+    }
+    // it is only used up by a dash that can start, an empty dash count keeps it
+    // waiting for a refill
+    if movement.dash.buffered > 0.
+        && movement.dash.dash_count > 0
+        && matches!(movement.dash.status, DashState::Ready)
+    {
         movement.dash.status = DashState::Started;
+        movement.dash.buffered = 0.;
+    }
+    movement.dash.buffered = (movement.dash.buffered - time.delta_seconds()).max(0.);
+
+    if temp_vec.x != 0. {
+        movement.facing = temp_vec.x.signum();
     }
     movement.directional = temp_vec;
 }
+
+type RespawnQuery<'w, 's> =
+    Query<'w, 's, (&'static mut Transform, &'static mut Velocity), With<PlayerMarker>>;
+
+// falling below the level or touching a hazard sends the player back to spawn
+fn respawn_on_death(
+    mut query: RespawnQuery,
+    level: Res<Level>,
+    mut stats: ResMut<LevelStats>,
+    mut sound_events: EventWriter<SoundEvent>,
+    mut deaths: EventWriter<PlayerDeath>,
+) {
+    let kill_y = level
+        .colliders
+        .iter()
+        .map(|item| item.pos.y)
+        .fold(f32::INFINITY, f32::min)
+        - KILL_PLANE_MARGIN;
+    for (mut transform, mut vel) in query.iter_mut() {
+        let pos = transform.translation.truncate();
+        let on_hazard = level
+            .collider_at(pos)
+            .is_some_and(|item| item.kind == CollisionKind::Hazard);
+        if pos.y < kill_y || on_hazard {
+            transform.translation.x = level.player_spawn_pos.x;
+            transform.translation.y = level.player_spawn_pos.y;
+            vel.0 = Vec2::ZERO;
+            stats.deaths += 1;
+            sound_events.send(SoundEvent::Death);
+            deaths.send(PlayerDeath);
+        }
+    }
+}
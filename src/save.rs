@@ -0,0 +1,513 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use bevy::prelude::*;
+use bevy::window::{PrimaryWindow, WindowMode};
+use serde_json::{json, Value};
+
+use crate::audio::AudioSettings;
+use crate::level::Level;
+use crate::player::{KeyBindings, PlayerDeath};
+use crate::speedrun::{finish_run, SpeedrunTimer};
+use crate::state::{on_level_start, GameState};
+use crate::util::*;
+
+pub struct SavePlugin;
+
+// bump these and add a step to the matching migrate function when a format changes
+const SAVE_VERSION: u64 = 1;
+const SETTINGS_VERSION: u64 = 1;
+
+// keys that can be written to and read back from the settings file
+const BINDABLE_KEYS: [KeyCode; 44] = [
+    KeyCode::ArrowUp,
+    KeyCode::ArrowDown,
+    KeyCode::ArrowLeft,
+    KeyCode::ArrowRight,
+    KeyCode::Space,
+    KeyCode::Enter,
+    KeyCode::ShiftLeft,
+    KeyCode::ShiftRight,
+    KeyCode::ControlLeft,
+    KeyCode::ControlRight,
+    KeyCode::AltLeft,
+    KeyCode::Tab,
+    KeyCode::KeyA,
+    KeyCode::KeyB,
+    KeyCode::KeyC,
+    KeyCode::KeyD,
+    KeyCode::KeyE,
+    KeyCode::KeyF,
+    KeyCode::KeyG,
+    KeyCode::KeyH,
+    KeyCode::KeyI,
+    KeyCode::KeyJ,
+    KeyCode::KeyK,
+    KeyCode::KeyL,
+    KeyCode::KeyM,
+    KeyCode::KeyN,
+    KeyCode::KeyO,
+    KeyCode::KeyP,
+    KeyCode::KeyQ,
+    KeyCode::KeyR,
+    KeyCode::KeyS,
+    KeyCode::KeyT,
+    KeyCode::KeyU,
+    KeyCode::KeyV,
+    KeyCode::KeyW,
+    KeyCode::KeyX,
+    KeyCode::KeyY,
+    KeyCode::KeyZ,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Comma,
+    KeyCode::Period,
+];
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        let settings = read_json(SETTINGS_FILE).map(migrate_settings);
+        let (audio, bindings, display) = match &settings {
+            Some(value) => (
+                AudioSettings::from_json(&value["audio"]),
+                key_bindings_from_json(&value["key_bindings"]),
+                DisplaySettings::from_json(&value["window_mode"]),
+            ),
+            None => default(),
+        };
+        let save = read_json(SAVE_FILE)
+            .map(migrate_save)
+            .map(|value| SaveData::from_json(&value))
+            .unwrap_or_default();
+
+        app.insert_resource(audio)
+            .insert_resource(bindings)
+            .insert_resource(display)
+            .insert_resource(save);
+        for schedule in on_level_start() {
+            app.add_systems(schedule, start_level_save);
+        }
+        app.add_systems(Startup, apply_window_mode)
+            .add_systems(
+                Update,
+                (
+                    toggle_window_mode,
+                    apply_window_mode.run_if(resource_changed::<DisplaySettings>),
+                    save_settings_on_change,
+                    count_deaths.run_if(on_event::<PlayerDeath>()),
+                ),
+            )
+            .add_systems(
+                OnEnter(GameState::LevelComplete),
+                complete_level_save.after(finish_run),
+            )
+            .add_systems(OnEnter(GameState::MainMenu), write_save);
+    }
+}
+
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq)]
+pub struct DisplaySettings {
+    pub window_mode: WindowMode,
+}
+
+impl DisplaySettings {
+    fn from_json(value: &Value) -> DisplaySettings {
+        let window_mode = match value.as_str() {
+            Some("borderless") => WindowMode::BorderlessFullscreen,
+            Some("fullscreen") => WindowMode::Fullscreen,
+            _ => WindowMode::Windowed,
+        };
+        DisplaySettings { window_mode }
+    }
+
+    fn to_json(self) -> Value {
+        let mode = match self.window_mode {
+            WindowMode::BorderlessFullscreen => "borderless",
+            WindowMode::Fullscreen | WindowMode::SizedFullscreen => "fullscreen",
+            WindowMode::Windowed => "windowed",
+        };
+        json!(mode)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    pub level: String,
+    pub position: Vec2,
+}
+
+#[derive(Resource, Clone, Debug, Default, PartialEq)]
+pub struct SaveData {
+    pub unlocked_levels: Vec<String>,
+    // collectible identifiers found so far, per level
+    pub collectibles: HashMap<String, Vec<String>>,
+    pub best_times: HashMap<String, f32>,
+    pub deaths: u64,
+    pub checkpoint: Option<Checkpoint>,
+}
+
+impl SaveData {
+    pub fn from_json(value: &Value) -> SaveData {
+        let strings = |value: &Value| -> Vec<String> {
+            value
+                .as_array()
+                .map(|items| {
+                    items
+                        .iter()
+                        .filter_map(|item| item.as_str())
+                        .map(|item| item.to_string())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let mut save = SaveData {
+            unlocked_levels: strings(&value["unlocked_levels"]),
+            deaths: value["deaths"].as_u64().unwrap_or(0),
+            ..default()
+        };
+        if let Some(levels) = value["collectibles"].as_object() {
+            for (level, found) in levels {
+                save.collectibles.insert(level.clone(), strings(found));
+            }
+        }
+        if let Some(levels) = value["best_times"].as_object() {
+            for (level, time) in levels {
+                if let Some(time) = time.as_f64() {
+                    save.best_times.insert(level.clone(), time as f32);
+                }
+            }
+        }
+        let checkpoint = &value["checkpoint"];
+        if let (Some(level), Some(x), Some(y)) = (
+            checkpoint["level"].as_str(),
+            checkpoint["x"].as_f64(),
+            checkpoint["y"].as_f64(),
+        ) {
+            save.checkpoint = Some(Checkpoint {
+                level: level.to_string(),
+                position: Vec2::new(x as f32, y as f32),
+            });
+        }
+        save
+    }
+
+    pub fn to_json(&self) -> Value {
+        let checkpoint = match &self.checkpoint {
+            Some(checkpoint) => json!({
+                "level": checkpoint.level,
+                "x": checkpoint.position.x,
+                "y": checkpoint.position.y,
+            }),
+            None => Value::Null,
+        };
+        json!({
+            "version": SAVE_VERSION,
+            "unlocked_levels": self.unlocked_levels,
+            "collectibles": self.collectibles,
+            "best_times": self.best_times,
+            "deaths": self.deaths,
+            "checkpoint": checkpoint,
+        })
+    }
+
+    pub fn unlock(&mut self, level: &str) {
+        if !self.unlocked_levels.iter().any(|item| item == level) {
+            self.unlocked_levels.push(level.to_string());
+        }
+    }
+}
+
+// writes next to the target and renames over it, so a crash mid write leaves
+// the previous file intact. The data is flushed to disk before the rename and
+// the directory after it, otherwise a power loss can still leave an empty file
+pub fn write_atomic(filepath: &str, contents: &str) -> io::Result<()> {
+    let path = Path::new(filepath);
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(dir)?;
+    let tmp = path.with_extension("tmp");
+    let mut file = fs::File::create(&tmp)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&tmp, path)?;
+    // directories can only be opened and synced like this on unix
+    #[cfg(unix)]
+    fs::File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+fn read_json(filepath: &str) -> Option<Value> {
+    let buffer = fs::read_to_string(filepath).ok()?;
+    match serde_json::from_str(buffer.as_str()) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            warn!("could not parse {} : {}", filepath, err);
+            None
+        }
+    }
+}
+
+fn version_of(value: &Value) -> u64 {
+    value["version"].as_u64().unwrap_or(0)
+}
+
+pub fn migrate_save(mut value: Value) -> Value {
+    if !value.is_object() {
+        warn!("save file is not an object, starting over");
+        value = json!({});
+    }
+    let mut version = version_of(&value);
+    if version > SAVE_VERSION {
+        warn!("save file version {} is newer than this build", version);
+    }
+    while version < SAVE_VERSION {
+        if version == 0 {
+            // a file without a version can be missing fields, fill them in so
+            // every version 1 file has the full layout
+            let defaults = SaveData::default().to_json();
+            for (key, default) in defaults.as_object().into_iter().flatten() {
+                if value[key].is_null() {
+                    value[key] = default.clone();
+                }
+            }
+        }
+        version += 1;
+    }
+    value["version"] = json!(version);
+    value
+}
+
+pub fn migrate_settings(mut value: Value) -> Value {
+    if !value.is_object() {
+        warn!("settings file is not an object, starting over");
+        value = json!({});
+    }
+    let mut version = version_of(&value);
+    while version < SETTINGS_VERSION {
+        if version == 0 {
+            // the first settings file only had "audio", keep it and fill in the rest
+            value["key_bindings"] = key_bindings_to_json(&KeyBindings::default());
+            value["window_mode"] = DisplaySettings::default().to_json();
+        }
+        version += 1;
+    }
+    value["version"] = json!(version);
+    value
+}
+
+fn key_name(key: KeyCode) -> String {
+    format!("{:?}", key)
+}
+
+fn key_from_name(name: &str) -> Option<KeyCode> {
+    BINDABLE_KEYS.into_iter().find(|key| key_name(*key) == name)
+}
+
+pub fn key_bindings_to_json(bindings: &KeyBindings) -> Value {
+    json!({
+        "up": key_name(bindings.up),
+        "down": key_name(bindings.down),
+        "left": key_name(bindings.left),
+        "right": key_name(bindings.right),
+        "jump": key_name(bindings.jump),
+        "dash": key_name(bindings.dash),
+        "grab": key_name(bindings.grab),
+        "grapple": key_name(bindings.grapple),
+    })
+}
+
+pub fn key_bindings_from_json(value: &Value) -> KeyBindings {
+    let mut bindings = KeyBindings::default();
+    let slots = [
+        ("up", &mut bindings.up),
+        ("down", &mut bindings.down),
+        ("left", &mut bindings.left),
+        ("right", &mut bindings.right),
+        ("jump", &mut bindings.jump),
+        ("dash", &mut bindings.dash),
+        ("grab", &mut bindings.grab),
+        ("grapple", &mut bindings.grapple),
+    ];
+    for (name, slot) in slots {
+        if let Some(key) = value[name].as_str().and_then(key_from_name) {
+            *slot = key;
+        }
+    }
+    bindings
+}
+
+fn write_save_data(save: &SaveData) {
+    if let Err(err) = write_atomic(SAVE_FILE, &save.to_json().to_string()) {
+        warn!("could not write save to {} : {}", SAVE_FILE, err);
+    }
+}
+
+fn write_save(save: Res<SaveData>) {
+    write_save_data(&save);
+}
+
+fn save_settings_on_change(
+    audio: Res<AudioSettings>,
+    bindings: Res<KeyBindings>,
+    display: Res<DisplaySettings>,
+) {
+    let changed = audio.is_changed() || bindings.is_changed() || display.is_changed();
+    let added = audio.is_added() || bindings.is_added() || display.is_added();
+    if !changed || added {
+        return;
+    }
+    let value = json!({
+        "version": SETTINGS_VERSION,
+        "audio": audio.to_json(),
+        "key_bindings": key_bindings_to_json(&bindings),
+        "window_mode": display.to_json(),
+    });
+    if let Err(err) = write_atomic(SETTINGS_FILE, &value.to_string()) {
+        warn!("could not write settings to {} : {}", SETTINGS_FILE, err);
+    }
+}
+
+fn toggle_window_mode(input: Res<ButtonInput<KeyCode>>, mut display: ResMut<DisplaySettings>) {
+    if input.just_pressed(KeyCode::F11) {
+        display.window_mode = match display.window_mode {
+            WindowMode::Windowed => WindowMode::BorderlessFullscreen,
+            _ => WindowMode::Windowed,
+        };
+    }
+}
+
+fn apply_window_mode(
+    display: Res<DisplaySettings>,
+    mut query: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    for mut window in query.iter_mut() {
+        window.mode = display.window_mode;
+    }
+}
+
+fn start_level_save(mut save: ResMut<SaveData>, level: Res<Level>) {
+    save.unlock(&level.name);
+    save.checkpoint = Some(Checkpoint {
+        level: level.name.clone(),
+        position: level.player_spawn_pos,
+    });
+    write_save_data(&save);
+}
+
+fn count_deaths(mut events: EventReader<PlayerDeath>, mut save: ResMut<SaveData>) {
+    save.deaths += events.read().count() as u64;
+}
+
+pub fn complete_level_save(
+    mut save: ResMut<SaveData>,
+    level: Res<Level>,
+    timer: Res<SpeedrunTimer>,
+) {
+    if timer.finished {
+        let time = timer.time();
+        let best = save.best_times.entry(level.name.clone()).or_insert(time);
+        *best = best.min(time);
+    }
+    save.checkpoint = None;
+    write_save_data(&save);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unversioned_save_is_filled_in() {
+        let value = migrate_save(json!({ "deaths": 3, "unlocked_levels": ["Level_0"] }));
+        assert_eq!(value["version"], json!(SAVE_VERSION));
+        assert_eq!(value["deaths"], json!(3));
+        assert_eq!(value["collectibles"], json!({}));
+        assert_eq!(value["best_times"], json!({}));
+        assert!(value.get("checkpoint").is_some());
+        let save = SaveData::from_json(&value);
+        assert_eq!(save.unlocked_levels, vec!["Level_0".to_string()]);
+    }
+
+    #[test]
+    fn current_save_is_left_alone() {
+        let mut save = SaveData {
+            deaths: 7,
+            ..default()
+        };
+        save.unlock("Level_0");
+        save.best_times.insert("Level_0".to_string(), 12.5);
+        save.checkpoint = Some(Checkpoint {
+            level: "Level_0".to_string(),
+            position: Vec2::new(16., 32.),
+        });
+        let value = save.to_json();
+        assert_eq!(migrate_save(value.clone()), value);
+        assert_eq!(SaveData::from_json(&value), save);
+        assert_eq!(migrate_save(json!([1, 2]))["version"], json!(SAVE_VERSION));
+    }
+
+    #[test]
+    fn first_settings_file_gets_bindings_and_window_mode() {
+        let value = migrate_settings(json!({ "audio": { "master": 0.5 } }));
+        assert_eq!(value["version"], json!(SETTINGS_VERSION));
+        assert_eq!(value["audio"]["master"], json!(0.5));
+        assert_eq!(
+            key_bindings_from_json(&value["key_bindings"]),
+            KeyBindings::default()
+        );
+        assert_eq!(value["window_mode"], json!("windowed"));
+    }
+
+    #[test]
+    fn settings_that_are_not_an_object_start_over() {
+        for broken in [json!([]), json!("audio"), json!(3)] {
+            let value = migrate_settings(broken);
+            assert_eq!(value["version"], json!(SETTINGS_VERSION));
+            assert_eq!(
+                key_bindings_from_json(&value["key_bindings"]),
+                KeyBindings::default()
+            );
+        }
+    }
+
+    #[test]
+    fn key_bindings_round_trip() {
+        let bindings = KeyBindings {
+            jump: KeyCode::KeyZ,
+            dash: KeyCode::KeyX,
+            grab: KeyCode::KeyC,
+            grapple: KeyCode::ShiftRight,
+            ..default()
+        };
+        let value = key_bindings_to_json(&bindings);
+        assert_eq!(value["jump"], json!("KeyZ"));
+        assert_eq!(key_bindings_from_json(&value), bindings);
+    }
+
+    #[test]
+    fn unknown_keys_keep_their_default() {
+        let bindings = key_bindings_from_json(&json!({ "jump": "F13", "dash": 4, "up": "KeyW" }));
+        assert_eq!(bindings.jump, KeyBindings::default().jump);
+        assert_eq!(bindings.dash, KeyBindings::default().dash);
+        assert_eq!(bindings.up, KeyCode::KeyW);
+    }
+
+    #[test]
+    fn write_atomic_replaces_the_file() {
+        let dir = std::env::temp_dir().join(format!("save_test_{}", std::process::id()));
+        let path = dir.join("save.json");
+        let filepath = path.to_str().unwrap();
+        write_atomic(filepath, "first").unwrap();
+        write_atomic(filepath, "second").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        assert!(!path.with_extension("tmp").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+use std::fs;
+
+use bevy::prelude::*;
+use serde_json::{json, Value};
+
+use crate::level::Level;
+use crate::save::write_atomic;
+use crate::state::{on_level_start, GameState, LevelEntity};
+use crate::util::*;
+
+pub struct SpeedrunPlugin;
+
+const AHEAD_COLOR: Color = Color::rgb(0.3, 1., 0.4);
+const BEHIND_COLOR: Color = Color::rgb(1., 0.4, 0.3);
+
+impl Plugin for SpeedrunPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SplitEvent>()
+            .insert_resource(PersonalBests::load(SPLITS_FILE))
+            .init_resource::<SpeedrunTimer>();
+        for schedule in on_level_start() {
+            app.add_systems(schedule, (reset_speedrun_timer, spawn_speedrun_text));
+        }
+        app.add_systems(
+            Update,
+            (
+                toggle_timing_mode,
+                (
+                    start_on_first_input,
+                    tick_wall_time,
+                    split_on_level_change,
+                    record_splits,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Playing)),
+                update_speedrun_text.after(record_splits).run_if(
+                    in_state(GameState::Playing).or_else(in_state(GameState::LevelComplete)),
+                ),
+            ),
+        )
+        .add_systems(
+            FixedUpdate,
+            tick_fixed_time.run_if(in_state(GameState::Playing)),
+        )
+        .add_systems(OnEnter(GameState::LevelComplete), finish_run);
+    }
+}
+
+// sent by gameplay code (finish entities, level transitions) to record a split
+#[derive(Event)]
+pub struct SplitEvent;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimingMode {
+    #[default]
+    WallTime,
+    FixedTick,
+}
+
+impl TimingMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimingMode::WallTime => "wall",
+            TimingMode::FixedTick => "fixed",
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct SpeedrunTimer {
+    pub mode: TimingMode,
+    pub running: bool,
+    pub finished: bool,
+    pub wall_time: f32,
+    pub fixed_ticks: u32,
+    pub fixed_timestep: f32,
+    // cumulative times at each split
+    pub splits: Vec<f32>,
+}
+
+impl SpeedrunTimer {
+    pub fn time(&self) -> f32 {
+        match self.mode {
+            TimingMode::WallTime => self.wall_time,
+            TimingMode::FixedTick => self.fixed_ticks as f32 * self.fixed_timestep,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct PersonalBest {
+    pub time: f32,
+    pub splits: Vec<f32>,
+}
+
+// best runs keyed by level name and timing mode, times from the two modes are
+// not comparable
+#[derive(Resource, Default)]
+pub struct PersonalBests(pub HashMap<String, PersonalBest>);
+
+impl PersonalBests {
+    pub fn key(level: &str, mode: TimingMode) -> String {
+        format!("{}:{}", level, mode.as_str())
+    }
+
+    pub fn load(filepath: &str) -> PersonalBests {
+        let mut bests = PersonalBests::default();
+        let Ok(buffer) = fs::read_to_string(filepath) else {
+            return bests;
+        };
+        let Ok(value) = serde_json::from_str::<Value>(buffer.as_str()) else {
+            warn!("could not parse {}, personal bests are reset", filepath);
+            return bests;
+        };
+        let Some(levels) = value["levels"].as_object() else {
+            return bests;
+        };
+        for (key, run) in levels {
+            let Some(time) = run["time"].as_f64() else {
+                continue;
+            };
+            let splits = run["splits"]
+                .as_array()
+                .map(|splits| {
+                    splits
+                        .iter()
+                        .filter_map(|split| split.as_f64())
+                        .map(|split| split as f32)
+                        .collect()
+                })
+                .unwrap_or_default();
+            bests.0.insert(
+                key.clone(),
+                PersonalBest {
+                    time: time as f32,
+                    splits,
+                },
+            );
+        }
+        bests
+    }
+
+    pub fn save(&self, filepath: &str) {
+        let mut levels = serde_json::Map::new();
+        for (key, run) in &self.0 {
+            levels.insert(
+                key.clone(),
+                json!({ "time": run.time, "splits": run.splits }),
+            );
+        }
+        let value = json!({ "levels": levels });
+        if let Err(err) = write_atomic(filepath, &value.to_string()) {
+            warn!("could not save splits to {} : {}", filepath, err);
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct SpeedrunTextMarker;
+
+fn reset_speedrun_timer(mut timer: ResMut<SpeedrunTimer>, time: Res<Time<Fixed>>) {
+    *timer = SpeedrunTimer {
+        mode: timer.mode,
+        fixed_timestep: time.timestep().as_secs_f32(),
+        ..default()
+    };
+}
+
+fn toggle_timing_mode(input: Res<ButtonInput<KeyCode>>, mut timer: ResMut<SpeedrunTimer>) {
+    // switching mid run would mix both clocks
+    if input.just_pressed(KeyCode::F4) && !timer.running {
+        timer.mode = match timer.mode {
+            TimingMode::WallTime => TimingMode::FixedTick,
+            TimingMode::FixedTick => TimingMode::WallTime,
+        };
+    }
+}
+
+fn start_on_first_input(input: Res<ButtonInput<KeyCode>>, mut timer: ResMut<SpeedrunTimer>) {
+    if timer.running || timer.finished {
+        return;
+    }
+    let started = input.get_just_pressed().any(|key| {
+        !matches!(
+            key,
+            KeyCode::Escape | KeyCode::F1 | KeyCode::F2 | KeyCode::F3 | KeyCode::F4
+        )
+    });
+    if started {
+        timer.running = true;
+    }
+}
+
+fn tick_wall_time(mut timer: ResMut<SpeedrunTimer>, time: Res<Time<Real>>) {
+    if timer.running {
+        timer.wall_time += time.delta_seconds();
+    }
+}
+
+fn tick_fixed_time(mut timer: ResMut<SpeedrunTimer>) {
+    if timer.running {
+        timer.fixed_ticks += 1;
+    }
+}
+
+fn split_on_level_change(level: Res<Level>, mut splits: EventWriter<SplitEvent>) {
+    if level.is_changed() && !level.is_added() {
+        splits.send(SplitEvent);
+    }
+}
+
+fn record_splits(mut events: EventReader<SplitEvent>, mut timer: ResMut<SpeedrunTimer>) {
+    for _ in events.read() {
+        if timer.running {
+            let time = timer.time();
+            timer.splits.push(time);
+        }
+    }
+}
+
+pub fn finish_run(
+    mut timer: ResMut<SpeedrunTimer>,
+    mut bests: ResMut<PersonalBests>,
+    level: Res<Level>,
+) {
+    if !timer.running {
+        return;
+    }
+    timer.running = false;
+    timer.finished = true;
+    let time = timer.time();
+    timer.splits.push(time);
+
+    let key = PersonalBests::key(&level.name, timer.mode);
+    let is_best = bests.0.get(&key).is_none_or(|best| time < best.time);
+    if is_best {
+        bests.0.insert(
+            key,
+            PersonalBest {
+                time,
+                splits: timer.splits.clone(),
+            },
+        );
+        bests.save(SPLITS_FILE);
+    }
+}
+
+fn spawn_speedrun_text(mut commands: Commands) {
+    let style = TextStyle {
+        font_size: 20.,
+        ..default()
+    };
+    commands.spawn((
+        TextBundle::from_sections([
+            TextSection::new("", style.clone()),
+            TextSection::new("", style),
+        ])
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(8.),
+            right: Val::Px(8.),
+            ..default()
+        }),
+        SpeedrunTextMarker,
+        LevelEntity,
+    ));
+}
+
+fn update_speedrun_text(
+    timer: Res<SpeedrunTimer>,
+    bests: Res<PersonalBests>,
+    level: Res<Level>,
+    mut query: Query<&mut Text, With<SpeedrunTextMarker>>,
+) {
+    let Ok(mut text) = query.get_single_mut() else {
+        return;
+    };
+    text.sections[0].value = format!("{} {:.2}", timer.mode.as_str(), timer.time());
+
+    // delta of the latest split against the same split of the personal best
+    let best = bests.0.get(&PersonalBests::key(&level.name, timer.mode));
+    let delta = best.and_then(|best| {
+        let index = timer.splits.len().checked_sub(1)?;
+        Some(timer.splits[index] - best.splits.get(index)?)
+    });
+    match delta {
+        Some(delta) => {
+            text.sections[1].value = format!("  {:+.2}", delta);
+            text.sections[1].style.color = if delta <= 0. {
+                AHEAD_COLOR
+            } else {
+                BEHIND_COLOR
+            };
+        }
+        None => text.sections[1].value.clear(),
+    }
+}
@@ -0,0 +1,212 @@
+use bevy::prelude::*;
+
+use crate::level::LevelStats;
+
+pub struct StatePlugin;
+
+#[derive(States, Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameState {
+    #[default]
+    Loading,
+    MainMenu,
+    Playing,
+    Paused,
+    LevelComplete,
+}
+
+// everything that belongs to the level being played (tiles, player, particles),
+// despawned when going back to the menu or leaving the level complete screen
+#[derive(Component)]
+pub struct LevelEntity;
+
+#[derive(Component)]
+pub struct MainMenuMarker;
+
+#[derive(Component)]
+pub struct PauseOverlayMarker;
+
+#[derive(Component)]
+pub struct LevelCompleteMarker;
+
+impl Plugin for StatePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_state::<GameState>()
+            .add_systems(
+                Update,
+                (
+                    main_menu_input.run_if(in_state(GameState::MainMenu)),
+                    playing_input.run_if(in_state(GameState::Playing)),
+                    paused_input.run_if(in_state(GameState::Paused)),
+                    level_complete_input.run_if(in_state(GameState::LevelComplete)),
+                ),
+            )
+            .add_systems(
+                OnEnter(GameState::MainMenu),
+                (spawn_main_menu, despawn_all::<LevelEntity>),
+            )
+            .add_systems(OnExit(GameState::MainMenu), despawn_all::<MainMenuMarker>)
+            .add_systems(
+                OnEnter(GameState::Paused),
+                (spawn_pause_overlay, pause_time),
+            )
+            .add_systems(
+                OnExit(GameState::Paused),
+                (despawn_all::<PauseOverlayMarker>, unpause_time),
+            )
+            .add_systems(OnEnter(GameState::LevelComplete), spawn_level_complete)
+            .add_systems(
+                OnExit(GameState::LevelComplete),
+                (
+                    despawn_all::<LevelCompleteMarker>,
+                    despawn_all::<LevelEntity>,
+                ),
+            );
+    }
+}
+
+// systems that set up a level run on these transitions, not on OnEnter(Playing),
+// so that resuming from pause does not respawn anything
+pub fn on_level_start() -> [OnTransition<GameState>; 2] {
+    [
+        OnTransition {
+            from: GameState::MainMenu,
+            to: GameState::Playing,
+        },
+        OnTransition {
+            from: GameState::LevelComplete,
+            to: GameState::Playing,
+        },
+    ]
+}
+
+pub fn despawn_all<T: Component>(query: Query<Entity, With<T>>, mut commands: Commands) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn pause_time(mut time: ResMut<Time<Virtual>>) {
+    time.pause();
+}
+
+fn unpause_time(mut time: ResMut<Time<Virtual>>) {
+    time.unpause();
+}
+
+fn main_menu_input(input: Res<ButtonInput<KeyCode>>, mut next_state: ResMut<NextState<GameState>>) {
+    if input.just_pressed(KeyCode::Enter) {
+        next_state.set(GameState::Playing);
+    }
+}
+
+fn playing_input(input: Res<ButtonInput<KeyCode>>, mut next_state: ResMut<NextState<GameState>>) {
+    if input.just_pressed(KeyCode::Escape) {
+        next_state.set(GameState::Paused);
+    }
+}
+
+fn paused_input(input: Res<ButtonInput<KeyCode>>, mut next_state: ResMut<NextState<GameState>>) {
+    if input.just_pressed(KeyCode::Escape) {
+        next_state.set(GameState::Playing);
+    } else if input.just_pressed(KeyCode::Backspace) {
+        next_state.set(GameState::MainMenu);
+    }
+}
+
+fn level_complete_input(
+    input: Res<ButtonInput<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if input.just_pressed(KeyCode::Enter) {
+        next_state.set(GameState::Playing);
+    } else if input.just_pressed(KeyCode::Escape) {
+        next_state.set(GameState::MainMenu);
+    }
+}
+
+fn spawn_screen<T: Component>(
+    commands: &mut Commands,
+    marker: T,
+    background: Color,
+    lines: &[(&str, f32)],
+) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(10.),
+                    ..default()
+                },
+                background_color: background.into(),
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            marker,
+        ))
+        .with_children(|parent| {
+            for (line, font_size) in lines {
+                parent.spawn(TextBundle::from_section(
+                    *line,
+                    TextStyle {
+                        font_size: *font_size,
+                        ..default()
+                    },
+                ));
+            }
+        });
+}
+
+fn spawn_main_menu(mut commands: Commands) {
+    spawn_screen(
+        &mut commands,
+        MainMenuMarker,
+        Color::BLACK,
+        &[("BASIC PLATFORMER", 48.), ("press enter to start", 20.)],
+    );
+}
+
+fn spawn_pause_overlay(mut commands: Commands) {
+    spawn_screen(
+        &mut commands,
+        PauseOverlayMarker,
+        Color::rgba(0., 0., 0., 0.6),
+        &[
+            ("PAUSED", 48.),
+            ("escape : resume", 20.),
+            ("backspace : main menu", 20.),
+        ],
+    );
+}
+
+fn spawn_level_complete(mut commands: Commands, stats: Res<LevelStats>) {
+    let minutes = (stats.elapsed / 60.) as u32;
+    let time = format!("time : {}:{:05.2}", minutes, stats.elapsed % 60.);
+    let deaths = format!("deaths : {}", stats.deaths);
+    let collected = format!(
+        "collected : {} / {}",
+        stats.collected, stats.total_collectibles
+    );
+    let strawberries = format!(
+        "strawberries : {} / {}",
+        stats.strawberries, stats.total_strawberries
+    );
+    spawn_screen(
+        &mut commands,
+        LevelCompleteMarker,
+        Color::rgba(0., 0., 0., 0.6),
+        &[
+            ("LEVEL COMPLETE", 48.),
+            (&time, 24.),
+            (&deaths, 24.),
+            (&collected, 24.),
+            (&strawberries, 24.),
+            ("enter : play again", 20.),
+            ("escape : main menu", 20.),
+        ],
+    );
+}
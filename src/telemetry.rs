@@ -0,0 +1,206 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+
+use crate::physics::*;
+use crate::player::PlayerMarker;
+use crate::state::GameState;
+use crate::util::*;
+
+pub struct TelemetryPlugin;
+
+// graph placement relative to the camera center, in world units
+const GRAPH_OFFSET: Vec2 = Vec2::new(-300., -170.);
+const GRAPH_SIZE: Vec2 = Vec2::new(200., 60.);
+
+impl Plugin for TelemetryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PhysicsTelemetry>()
+            .add_systems(
+                Update,
+                record_telemetry
+                    .after(update_physics)
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                Update,
+                (
+                    telemetry_input,
+                    draw_telemetry_graph.run_if(telemetry_graph_enabled),
+                ),
+            )
+            .add_systems(OnExit(GameState::Playing), clear_on_level_exit);
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct TelemetrySample {
+    pub time: f32,
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub acceleration: Vec2,
+    pub friction: f32,
+    pub gravity: f32,
+    pub grounded: bool,
+    pub dash_state: DashState,
+}
+
+// ring buffer of the last TELEMETRY_CAPACITY physics ticks
+#[derive(Resource)]
+pub struct PhysicsTelemetry {
+    pub samples: VecDeque<TelemetrySample>,
+    pub show_graph: bool,
+}
+
+impl Default for PhysicsTelemetry {
+    fn default() -> Self {
+        PhysicsTelemetry {
+            samples: VecDeque::with_capacity(TELEMETRY_CAPACITY),
+            show_graph: false,
+        }
+    }
+}
+
+impl PhysicsTelemetry {
+    pub fn push(&mut self, sample: TelemetrySample) {
+        if self.samples.len() == TELEMETRY_CAPACITY {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "time,pos_x,pos_y,vel_x,vel_y,accel_x,accel_y,friction,gravity,grounded,dash_state\n",
+        );
+        for s in &self.samples {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{},{},{},{},{},{:?}",
+                s.time,
+                s.position.x,
+                s.position.y,
+                s.velocity.x,
+                s.velocity.y,
+                s.acceleration.x,
+                s.acceleration.y,
+                s.friction,
+                s.gravity,
+                s.grounded,
+                s.dash_state
+            );
+        }
+        csv
+    }
+
+    pub fn dump_csv(&self) -> std::io::Result<String> {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let filepath = format!("telemetry_{}.csv", secs);
+        fs::write(&filepath, self.to_csv())?;
+        Ok(filepath)
+    }
+}
+
+// samples only make sense within one play session, pausing keeps them
+fn clear_on_level_exit(mut telemetry: ResMut<PhysicsTelemetry>, state: Res<State<GameState>>) {
+    if !matches!(state.get(), GameState::Paused) {
+        telemetry.samples.clear();
+    }
+}
+
+type TelemetryQuery<'a> = (
+    &'a Transform,
+    &'a Velocity,
+    &'a Acceleration,
+    &'a Friction,
+    &'a Gravity,
+    &'a Grounded,
+    &'a Movement,
+);
+
+fn record_telemetry(
+    query: Query<TelemetryQuery, With<PlayerMarker>>,
+    mut telemetry: ResMut<PhysicsTelemetry>,
+    time: Res<Time>,
+) {
+    for (transform, vel, accel, friction, gravity, grounded, movement) in query.iter() {
+        telemetry.push(TelemetrySample {
+            time: time.elapsed_seconds(),
+            position: transform.translation.truncate(),
+            velocity: vel.0,
+            acceleration: accel.0,
+            friction: friction.0,
+            gravity: gravity.0,
+            grounded: grounded.0,
+            dash_state: movement.dash.status,
+        });
+    }
+}
+
+fn telemetry_graph_enabled(telemetry: Res<PhysicsTelemetry>) -> bool {
+    telemetry.show_graph
+}
+
+fn telemetry_input(input: Res<ButtonInput<KeyCode>>, mut telemetry: ResMut<PhysicsTelemetry>) {
+    if input.just_pressed(KeyCode::F2) {
+        telemetry.show_graph = !telemetry.show_graph;
+    }
+    if input.just_pressed(KeyCode::F3) {
+        match telemetry.dump_csv() {
+            Ok(filepath) => info!("telemetry written to {}", filepath),
+            Err(err) => warn!("could not write telemetry : {}", err),
+        }
+    }
+}
+
+fn draw_telemetry_graph(
+    mut gizmos: Gizmos,
+    telemetry: Res<PhysicsTelemetry>,
+    query_cam: Query<&Transform, With<CameraMarker>>,
+) {
+    let Ok(cam) = query_cam.get_single() else {
+        return;
+    };
+    let origin = cam.translation.truncate() + GRAPH_OFFSET;
+    let count = telemetry.samples.len().max(2) - 1;
+    let scale_y = GRAPH_SIZE.y * 0.5 / INITIAL_VEL_DASH.max(MAX_VEL_PLAYER);
+    let point = |i: usize, v: f32| {
+        origin
+            + Vec2::new(
+                GRAPH_SIZE.x * i as f32 / count as f32,
+                GRAPH_SIZE.y * 0.5 + v.clamp(-INITIAL_VEL_DASH, INITIAL_VEL_DASH) * scale_y,
+            )
+    };
+
+    gizmos.rect_2d(origin + GRAPH_SIZE * 0.5, 0., GRAPH_SIZE, Color::GRAY);
+    gizmos.line_2d(
+        origin + Vec2::new(0., GRAPH_SIZE.y * 0.5),
+        origin + Vec2::new(GRAPH_SIZE.x, GRAPH_SIZE.y * 0.5),
+        Color::DARK_GRAY,
+    );
+    gizmos.linestrip_2d(
+        telemetry
+            .samples
+            .iter()
+            .enumerate()
+            .map(|(i, s)| point(i, s.velocity.x)),
+        Color::RED,
+    );
+    gizmos.linestrip_2d(
+        telemetry
+            .samples
+            .iter()
+            .enumerate()
+            .map(|(i, s)| point(i, s.velocity.y)),
+        Color::GREEN,
+    );
+
+    // jump arc, the recent path of the player in world space
+    gizmos.linestrip_2d(telemetry.samples.iter().map(|s| s.position), Color::YELLOW);
+}
@@ -10,8 +10,70 @@ pub const INITIAL_VEL_DASH: f32 = 880.;
 pub const INITIAL_ACCEL_DASH: f32 = 3800.;
 pub const DEFAULT_DASH_COUNT: usize = 2;
 pub const DEFAULT_DASH_DURATION: f32 = 0.2;
+pub const KILL_PLANE_MARGIN: f32 = 160.;
+pub const TELEMETRY_CAPACITY: usize = 600;
+pub const LEVEL_FILE: &str = "level_1.json";
+pub const PLAYER_TEXTURE: &str = "tile_0022.png";
+pub const SPLITS_FILE: &str = "splits.json";
+pub const GHOSTS_DIR: &str = "ghosts";
+pub const GHOST_ALPHA: f32 = 0.25;
+pub const SETTINGS_FILE: &str = "settings.json";
+pub const SAVE_FILE: &str = "save.json";
+pub const SFX_MAX_LIFETIME: f32 = 3.;
+pub const MUSIC_CROSSFADE_DURATION: f32 = 1.5;
+pub const STRAWBERRY_SAFE_TIME: f32 = 0.3;
+pub const ICE_FRICTION_SCALE: f32 = 0.1;
+pub const TILE_LAYER_Z_STEP: f32 = 2.;
+pub const CORNER_CORRECTION: f32 = 4.;
+pub const LEDGE_ASSIST: f32 = 4.;
+pub const DASH_BUFFER_TIME: f32 = 0.1;
+pub const DASH_FREEZE_TIME: f32 = 0.05;
+pub const SUPER_JUMP_SPEED: f32 = 2.;
+pub const SUPER_JUMP_HEIGHT: f32 = 0.8;
+pub const WAVEDASH_SPEED: f32 = 2.2;
+pub const WALL_JUMP_SPEED: f32 = 1.2;
+pub const WALL_JUMP_HEIGHT: f32 = 1.1;
+pub const DASH_JUMP_GRACE: f32 = 0.1;
+pub const JUMP_PRESS_WINDOW: f32 = 0.1;
+pub const MOMENTUM_DECAY: f32 = 240.;
+pub const DOUBLE_JUMP_HEIGHT: f32 = 0.9;
+pub const GLIDE_FALL_SPEED: f32 = 40.;
+pub const GROUND_POUND_SPEED: f32 = 400.;
+pub const PLAYER_HALF_SIZE: Vec2 = Vec2::splat(8.);
+pub const CROUCH_HALF_HEIGHT: f32 = 4.;
+// running at least this fast when crouching starts a slide
+pub const SLIDE_MIN_SPEED: f32 = 120.;
+pub const SLIDE_FRICTION_SCALE: f32 = 0.2;
+pub const CLIMB_SPEED: f32 = 80.;
+pub const CLIMB_JUMP_HEIGHT: f32 = 0.8;
+// how far from the side of the hitbox a wall still counts as touching
+pub const GRAB_REACH: f32 = 3.;
+pub const WALL_CLIMB_SPEED: f32 = 50.;
+// stamina is in seconds of holding still, climbing and jumping cost more
+pub const STAMINA_MAX: f32 = 4.;
+pub const STAMINA_CLIMB_COST: f32 = 2.;
+pub const STAMINA_JUMP_COST: f32 = 1.;
+pub const STAMINA_WARNING: f32 = 1.;
+// fall speed while holding a wall without stamina
+pub const WALL_SLIP_SPEED: f32 = 60.;
+pub const GRAPPLE_RANGE: f32 = 160.;
+pub const GRAPPLE_MIN_LENGTH: f32 = 16.;
+// how fast up and down shorten and lengthen the rope
+pub const GRAPPLE_REEL_SPEED: f32 = 80.;
+pub const WATER_SWIM_SPEED: f32 = 90.;
+// how fast swimming velocity changes, per second
+pub const WATER_SWIM_ACCEL: f32 = 600.;
+// rise speed when not swimming up or down
+pub const WATER_BUOYANCY: f32 = 30.;
+// share of the velocity kept when falling into water
+pub const WATER_ENTRY_DAMPING: f32 = 0.4;
+// jumping out at the surface, a multiple of a normal jump
+pub const WATER_JUMP_BOOST: f32 = 1.3;
+pub const WATER_DASH_SCALE: f32 = 0.5;
+// stick input below this length is ignored
+pub const STICK_DEADZONE: f32 = 0.3;
 
-#[derive(Component)]
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
 pub struct HitBox {
     pub half_size: Vec2,
 }
@@ -0,0 +1,219 @@
+use std::time::Duration;
+
+use bevy::ecs::system::EntityCommands;
+use bevy::prelude::*;
+
+use crate::audio::SoundEvent;
+use crate::entities::{LdtkEntity, RegisterLdtkEntity};
+use crate::level::Level;
+use crate::physics::{
+    refill_dash_and_stamina, Collider, CollisionKind, DashState, Gravity, Movement, PhysicsSet,
+    Stamina, Velocity,
+};
+use crate::player::{PlayerDeath, TrailParticle};
+use crate::state::{on_level_start, GameState, LevelEntity};
+use crate::util::*;
+
+pub struct WaterPlugin;
+
+const WATER_COLOR: Color = Color::rgba(0.2, 0.45, 0.9, 0.45);
+const SPLASH_COLOR: Color = Color::rgb(0.7, 0.85, 1.);
+const SPLASH_PARTICLE_COUNT: usize = 10;
+const SPLASH_PARTICLE_SPEED: f32 = 90.;
+
+impl Plugin for WaterPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_ldtk_entity("Water", spawn_water);
+        for schedule in on_level_start() {
+            app.add_systems(schedule, add_water_colliders);
+        }
+        app.add_systems(
+            Update,
+            (
+                swim.in_set(PhysicsSet::PreStep),
+                (
+                    move_splash_particles,
+                    leave_water_on_death
+                        .run_if(on_event::<PlayerDeath>())
+                        .before(swim),
+                )
+                    .run_if(in_state(GameState::Playing)),
+            ),
+        );
+    }
+}
+
+// velocity of a splash droplet, it falls and fades like a trail particle
+#[derive(Component)]
+pub struct SplashParticle(pub Vec2);
+
+fn spawn_water(entity: &mut EntityCommands, item: &LdtkEntity, _: &AssetServer) {
+    entity.insert((
+        Sprite {
+            color: WATER_COLOR,
+            custom_size: Some(item.size),
+            ..default()
+        },
+        Handle::<Image>::default(),
+    ));
+}
+
+// water entities join the IntGrid water
+fn add_water_colliders(mut level: ResMut<Level>) {
+    level
+        .bypass_change_detection()
+        .add_entity_colliders(&["Water"], CollisionKind::Water);
+}
+
+fn water_at(colliders: &[Collider], point: Vec2) -> Option<&Collider> {
+    colliders
+        .iter()
+        .find(|item| item.kind == CollisionKind::Water && item.contains(point))
+}
+
+// droplets thrown up from the surface at `pos`
+fn spawn_splash(commands: &mut Commands, pos: Vec2) {
+    for i in 0..SPLASH_PARTICLE_COUNT {
+        // spread over a fan pointing up, the outer ones a bit slower
+        let t = i as f32 / (SPLASH_PARTICLE_COUNT - 1) as f32;
+        let angle = std::f32::consts::PI * (0.2 + 0.6 * t);
+        let speed = SPLASH_PARTICLE_SPEED * (1. - 0.5 * (t - 0.5).abs());
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: SPLASH_COLOR,
+                    custom_size: Some(Vec2::splat(2.)),
+                    ..default()
+                },
+                transform: Transform::from_translation(pos.extend(1.)),
+                ..default()
+            },
+            SplashParticle(Vec2::from_angle(angle) * speed),
+            TrailParticle(Timer::new(Duration::from_secs_f32(0.4), TimerMode::Once)),
+            LevelEntity,
+        ));
+    }
+}
+
+fn move_splash_particles(time: Res<Time>, mut query: Query<(&mut SplashParticle, &mut Transform)>) {
+    for (mut particle, mut transform) in query.iter_mut() {
+        particle.0.y -= GRAVITY_DEFAULT * time.delta_seconds();
+        transform.translation += (particle.0 * time.delta_seconds()).extend(0.);
+    }
+}
+
+type SwimQuery<'a> = (
+    &'a Transform,
+    &'a HitBox,
+    &'a mut Velocity,
+    &'a mut Gravity,
+    &'a mut Movement,
+    &'a mut Stamina,
+);
+
+// the center of the player decides whether it is in the water. Underwater
+// gravity is replaced by a slow rise, the directional input swims and a jump
+// with the head above the surface leaps out
+fn swim(
+    mut commands: Commands,
+    level: Res<Level>,
+    time: Res<Time>,
+    mut query: Query<SwimQuery>,
+    mut sound_events: EventWriter<SoundEvent>,
+) {
+    for (transform, hitbox, mut vel, mut gravity, mut movement, mut stamina) in query.iter_mut() {
+        let center = transform.translation.truncate();
+        let feet = center - Vec2::new(0., hitbox.half_size.y);
+        let head = center + Vec2::new(0., hitbox.half_size.y);
+        let dashing = matches!(movement.dash.status, DashState::Dashing);
+
+        let water = water_at(&level.colliders, center);
+        if water.is_some() != movement.swimming {
+            // splash where the surface is, leaving through the side has none
+            let surface = water.or_else(|| water_at(&level.colliders, feet));
+            if let Some(surface) = surface {
+                spawn_splash(&mut commands, Vec2::new(center.x, surface.top()));
+            }
+            movement.swimming = water.is_some();
+            if movement.swimming {
+                movement.climbing = false;
+                movement.grabbing = false;
+                if !dashing {
+                    vel.0 *= WATER_ENTRY_DAMPING;
+                }
+            } else if !dashing {
+                gravity.0 = GRAVITY_DEFAULT;
+            }
+        }
+        // a dash keeps its own velocity, underwater it is weaker but refills
+        if !movement.swimming || dashing {
+            continue;
+        }
+        refill_dash_and_stamina(&mut movement, &mut stamina);
+
+        let at_surface = water_at(&level.colliders, head).is_none();
+        if movement.jump && at_surface {
+            movement.jump = false;
+            gravity.0 = GRAVITY_DEFAULT;
+            vel.0.y = JMP_VEL_PLAYER * WATER_JUMP_BOOST;
+            sound_events.send(SoundEvent::Jump);
+            continue;
+        }
+        if vel.0.y > WATER_SWIM_SPEED {
+            // still carried out by a leap or a dash
+            continue;
+        }
+
+        gravity.0 = 0.;
+        let mut target = movement.directional.clamp_length_max(1.) * WATER_SWIM_SPEED;
+        if movement.directional.y == 0. {
+            target.y = WATER_BUOYANCY;
+        }
+        if at_surface {
+            // float with the head out instead of bobbing through the surface
+            target.y = target.y.min(0.);
+        }
+        let change = (target - vel.0).clamp_length_max(WATER_SWIM_ACCEL * time.delta_seconds());
+        vel.0 += change;
+    }
+}
+
+fn leave_water_on_death(mut query: Query<(&mut Gravity, &mut Movement)>) {
+    for (mut gravity, mut movement) in query.iter_mut() {
+        if movement.swimming {
+            movement.swimming = false;
+            gravity.0 = GRAVITY_DEFAULT;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::physics::tests::{physics_world, step, test_level};
+    use crate::physics::PhysicsConfig;
+
+    #[test]
+    fn swimming_refills_dash_and_stamina() {
+        let level = test_level(&["~~~~", "~~~~", "~~~~", "####"]);
+        let mut world = physics_world(
+            level,
+            PhysicsConfig::default(),
+            Vec2::new(32., 40.),
+            Vec2::ZERO,
+        );
+        let mut query = world.query::<(&mut Movement, &mut Stamina)>();
+        for (mut movement, mut stamina) in query.iter_mut(&mut world) {
+            movement.dash.dash_count = 0;
+            stamina.0 = 0.;
+        }
+        world.run_system_once(swim);
+        step(&mut world, 1);
+        let (movement, stamina) = world.query::<(&Movement, &Stamina)>().single(&world);
+        assert!(movement.swimming);
+        assert_eq!(movement.dash.dash_count, DEFAULT_DASH_COUNT);
+        assert_eq!(stamina.0, STAMINA_MAX);
+    }
+}